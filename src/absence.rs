@@ -0,0 +1,158 @@
+use std::time::Duration;
+use std::time::Instant;
+
+// How a game against an opponent who left is ended
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Claim {
+    Victory,
+    Draw,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AbsenceAction {
+    Abort,
+    Claim(Claim),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AbsencePolicy {
+    // Wait for the opponent's first move before aborting, None waits forever
+    pub abort_after: Option<Duration>,
+    pub claim: Claim,
+}
+
+impl Default for AbsencePolicy {
+    fn default() -> Self {
+        AbsencePolicy {
+            abort_after: Some(Duration::from_secs(60)),
+            claim: Claim::Victory,
+        }
+    }
+}
+
+impl AbsencePolicy {
+    // `lichess_abort_seconds` (0 never aborts) and `lichess_gone_claim` (victory or draw)
+    pub fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Self {
+        let defaults = AbsencePolicy::default();
+        let abort_after = match var("lichess_abort_seconds").map(|s| s.trim().parse::<u64>()) {
+            Some(Ok(0)) => None,
+            Some(Ok(seconds)) => Some(Duration::from_secs(seconds)),
+            _ => defaults.abort_after,
+        };
+        let claim = match var("lichess_gone_claim").as_deref().map(str::trim) {
+            Some("draw") => Claim::Draw,
+            Some("victory") => Claim::Victory,
+            _ => defaults.claim,
+        };
+        AbsencePolicy { abort_after, claim }
+    }
+}
+
+// Timers for one game: aborting when the opponent never starts, claiming
+// once Lichess lets the bot end a game the opponent walked away from
+#[derive(Debug)]
+pub struct AbsenceWatch {
+    policy: AbsencePolicy,
+    abort_at: Option<Instant>,
+    claim_at: Option<Instant>,
+}
+
+impl AbsenceWatch {
+    pub fn new(policy: AbsencePolicy) -> Self {
+        AbsenceWatch {
+            policy,
+            abort_at: None,
+            claim_at: None,
+        }
+    }
+
+    // Called for every state. The abort timer runs while the opponent is
+    // to make their first move, and nothing runs once the game is over.
+    pub fn update(&mut self, running: bool, awaiting_first_move: bool, now: Instant) {
+        if !running {
+            self.abort_at = None;
+            self.claim_at = None;
+        } else if !awaiting_first_move {
+            self.abort_at = None;
+        } else if self.abort_at.is_none() {
+            self.abort_at = self.policy.abort_after.map(|after| now + after);
+        }
+    }
+
+    pub fn opponent_gone(&mut self, gone: bool, claim_in_seconds: Option<u64>, now: Instant) {
+        self.claim_at = match (gone, claim_in_seconds) {
+            (true, Some(seconds)) => Some(now + Duration::from_secs(seconds)),
+            _ => None,
+        };
+    }
+
+    // When the game loop has to wake up next
+    pub fn deadline(&self) -> Option<Instant> {
+        match (self.abort_at, self.claim_at) {
+            (Some(abort), Some(claim)) => Some(abort.min(claim)),
+            (abort, claim) => abort.or(claim),
+        }
+    }
+
+    // The action whose time has come, each one is handed out once
+    pub fn due(&mut self, now: Instant) -> Option<AbsenceAction> {
+        if self.abort_at.is_some_and(|at| at <= now) {
+            self.abort_at = None;
+            return Some(AbsenceAction::Abort);
+        }
+        if self.claim_at.is_some_and(|at| at <= now) {
+            self.claim_at = None;
+            return Some(AbsenceAction::Claim(self.policy.claim));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aborts_when_the_first_move_never_comes() {
+        let mut watch = AbsenceWatch::new(AbsencePolicy::default());
+        let now = Instant::now();
+        watch.update(true, true, now);
+        // Later states do not push the deadline back
+        watch.update(true, true, now + Duration::from_secs(30));
+        assert_eq!(watch.deadline(), Some(now + Duration::from_secs(60)));
+        assert_eq!(watch.due(now + Duration::from_secs(59)), None);
+        assert_eq!(
+            watch.due(now + Duration::from_secs(60)),
+            Some(AbsenceAction::Abort)
+        );
+        assert_eq!(watch.due(now + Duration::from_secs(61)), None);
+
+        watch.update(true, true, now);
+        watch.update(true, false, now);
+        assert_eq!(watch.deadline(), None);
+    }
+
+    #[test]
+    fn claims_once_lichess_allows_it() {
+        let policy = AbsencePolicy::from_vars(|name| match name {
+            "lichess_gone_claim" => Some("draw".to_string()),
+            "lichess_abort_seconds" => Some("0".to_string()),
+            _ => None,
+        });
+        assert_eq!(policy.abort_after, None);
+        let mut watch = AbsenceWatch::new(policy);
+        let now = Instant::now();
+        watch.update(true, true, now);
+        watch.opponent_gone(true, Some(10), now);
+        assert_eq!(watch.deadline(), Some(now + Duration::from_secs(10)));
+        // Coming back cancels the claim
+        watch.opponent_gone(false, None, now);
+        assert_eq!(watch.deadline(), None);
+
+        watch.opponent_gone(true, Some(0), now);
+        assert_eq!(watch.due(now), Some(AbsenceAction::Claim(Claim::Draw)));
+        watch.opponent_gone(true, Some(5), now);
+        watch.update(false, false, now);
+        assert_eq!(watch.deadline(), None);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::engine::Score;
+use std::str::FromStr;
+
+// When the bot gives up or settles for a draw. Scores are centipawns from
+// the bot's side, as reported by the engine after each of its moves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdjudicationConfig {
+    // Resign once the score stays below -resign_score, None never resigns
+    pub resign_score: Option<i32>,
+    pub resign_moves: u32,
+    // Offer and accept draws while the score stays within ±draw_score,
+    // None only agrees to draws the tablebases confirm
+    pub draw_score: Option<i32>,
+    pub draw_moves: u32,
+    // Full moves played before a draw is offered or accepted on score
+    pub draw_after: u32,
+}
+
+impl Default for AdjudicationConfig {
+    fn default() -> Self {
+        AdjudicationConfig {
+            resign_score: None,
+            resign_moves: 3,
+            draw_score: None,
+            draw_moves: 5,
+            draw_after: 40,
+        }
+    }
+}
+
+impl AdjudicationConfig {
+    // `lichess_resign_score`, `lichess_resign_moves`, `lichess_draw_score`,
+    // `lichess_draw_moves` and `lichess_draw_after`. Scores are margins, so
+    // -800 and 800 mean the same.
+    pub fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Self {
+        let defaults = AdjudicationConfig::default();
+        let score = |name: &str| parse_setting::<i32>(name, var(name)).map(i32::saturating_abs);
+        let count = |name: &str| parse_setting::<u32>(name, var(name));
+        AdjudicationConfig {
+            resign_score: score("lichess_resign_score"),
+            resign_moves: count("lichess_resign_moves")
+                .unwrap_or(defaults.resign_moves)
+                .max(1),
+            draw_score: score("lichess_draw_score"),
+            draw_moves: count("lichess_draw_moves")
+                .unwrap_or(defaults.draw_moves)
+                .max(1),
+            draw_after: count("lichess_draw_after").unwrap_or(defaults.draw_after),
+        }
+    }
+}
+
+// A value that does not parse is reported instead of quietly turning the
+// setting off
+fn parse_setting<T: FromStr>(name: &str, value: Option<String>) -> Option<T> {
+    let value = value?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            println!("Ignoring {}={}, not a valid number", name, value);
+            None
+        }
+    }
+}
+
+// Streaks of hopeless and level scores over one game
+#[derive(Debug)]
+pub struct Adjudicator {
+    config: AdjudicationConfig,
+    losing_moves: u32,
+    level_moves: u32,
+    tablebase_draw: bool,
+    // The last move reached the first tablebase draw of the game
+    new_tablebase_draw: bool,
+}
+
+impl Adjudicator {
+    pub fn new(config: AdjudicationConfig) -> Self {
+        Adjudicator {
+            config,
+            losing_moves: 0,
+            level_moves: 0,
+            tablebase_draw: false,
+            new_tablebase_draw: false,
+        }
+    }
+
+    // Called after each of the bot's moves. `full_moves` counts the moves
+    // played in the game, `tablebase_draw` whether the position is a known draw.
+    pub fn record(&mut self, score: Option<Score>, full_moves: u32, tablebase_draw: bool) {
+        self.new_tablebase_draw = tablebase_draw && !self.tablebase_draw;
+        self.tablebase_draw = tablebase_draw;
+        let losing = match (score, self.config.resign_score) {
+            (Some(Score::Mate(moves)), Some(_)) => moves < 0,
+            (Some(Score::Centipawns(cp)), Some(limit)) => cp < -limit,
+            _ => false,
+        };
+        let level = match (score, self.config.draw_score) {
+            (Some(Score::Centipawns(cp)), Some(window)) => {
+                cp.abs() <= window && full_moves >= self.config.draw_after
+            }
+            _ => false,
+        };
+        self.losing_moves = if losing { self.losing_moves + 1 } else { 0 };
+        self.level_moves = if level { self.level_moves + 1 } else { 0 };
+    }
+
+    // Moves were taken back, the scores behind the streaks no longer apply
+    pub fn reset(&mut self) {
+        self.losing_moves = 0;
+        self.level_moves = 0;
+    }
+
+    pub fn should_resign(&self) -> bool {
+        self.losing_moves >= self.config.resign_moves
+    }
+
+    // Whether an offer from the opponent is taken
+    pub fn accepts_draw(&self) -> bool {
+        self.tablebase_draw || self.level_moves >= self.config.draw_moves
+    }
+
+    // Offers go out every `draw_moves` moves while the game stays level,
+    // and once when the tablebases first show a draw
+    pub fn should_offer_draw(&self) -> bool {
+        self.new_tablebase_draw
+            || (self.level_moves > 0 && self.level_moves.is_multiple_of(self.config.draw_moves))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resigns_after_a_run_of_lost_scores() {
+        let mut adjudicator = Adjudicator::new(AdjudicationConfig {
+            resign_score: Some(500),
+            resign_moves: 2,
+            ..AdjudicationConfig::default()
+        });
+        adjudicator.record(Some(Score::Centipawns(-900)), 20, false);
+        assert!(!adjudicator.should_resign());
+        adjudicator.record(Some(Score::Centipawns(-100)), 21, false);
+        adjudicator.record(Some(Score::Centipawns(-900)), 22, false);
+        assert!(!adjudicator.should_resign());
+        adjudicator.record(Some(Score::Mate(-4)), 23, false);
+        assert!(adjudicator.should_resign());
+        assert!(!adjudicator.accepts_draw());
+    }
+
+    #[test]
+    fn draws_level_games_after_enough_moves() {
+        let mut adjudicator = Adjudicator::new(AdjudicationConfig {
+            draw_score: Some(20),
+            draw_moves: 2,
+            draw_after: 30,
+            ..AdjudicationConfig::default()
+        });
+        adjudicator.record(Some(Score::Centipawns(0)), 29, false);
+        adjudicator.record(Some(Score::Centipawns(0)), 29, false);
+        assert!(!adjudicator.accepts_draw());
+        adjudicator.record(Some(Score::Centipawns(10)), 30, false);
+        adjudicator.record(Some(Score::Centipawns(-15)), 31, false);
+        assert!(adjudicator.accepts_draw());
+        assert!(adjudicator.should_offer_draw());
+        adjudicator.record(Some(Score::Centipawns(5)), 32, false);
+        assert!(!adjudicator.should_offer_draw());
+        adjudicator.record(Some(Score::Centipawns(300)), 33, false);
+        assert!(!adjudicator.accepts_draw());
+        // Known draws are taken whatever the score settings
+        adjudicator.record(Some(Score::Centipawns(300)), 34, true);
+        assert!(adjudicator.accepts_draw());
+        assert!(adjudicator.should_offer_draw());
+        adjudicator.record(Some(Score::Centipawns(300)), 35, true);
+        assert!(!adjudicator.should_offer_draw());
+        assert!(!adjudicator.should_resign());
+    }
+
+    #[test]
+    fn negative_scores_are_margins_too() {
+        let config = AdjudicationConfig::from_vars(|name| match name {
+            "lichess_resign_score" => Some("-800".to_string()),
+            "lichess_draw_score" => Some(" 15 ".to_string()),
+            "lichess_draw_moves" => Some("many".to_string()),
+            _ => None,
+        });
+        assert_eq!(config.resign_score, Some(800));
+        assert_eq!(config.draw_score, Some(15));
+        assert_eq!(config.draw_moves, AdjudicationConfig::default().draw_moves);
+    }
+}
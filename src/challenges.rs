@@ -0,0 +1,415 @@
+use crate::lichess::Challenge;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+const SPEEDS: [&str; 6] = [
+    "ultraBullet",
+    "bullet",
+    "blitz",
+    "rapid",
+    "classical",
+    "correspondence",
+];
+
+// Reason codes understood by /api/challenge/{id}/decline
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DeclineReason {
+    Generic,
+    Later,
+    TooFast,
+    TooSlow,
+    TimeControl,
+    Rated,
+    Casual,
+    Standard,
+    Variant,
+    NoBot,
+    OnlyBot,
+}
+
+impl DeclineReason {
+    pub fn key(self) -> &'static str {
+        match self {
+            DeclineReason::Generic => "generic",
+            DeclineReason::Later => "later",
+            DeclineReason::TooFast => "tooFast",
+            DeclineReason::TooSlow => "tooSlow",
+            DeclineReason::TimeControl => "timeControl",
+            DeclineReason::Rated => "rated",
+            DeclineReason::Casual => "casual",
+            DeclineReason::Standard => "standard",
+            DeclineReason::Variant => "variant",
+            DeclineReason::NoBot => "noBot",
+            DeclineReason::OnlyBot => "onlyBot",
+        }
+    }
+}
+
+// Which challenges the bot takes on
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChallengePolicy {
+    pub variants: Vec<String>,
+    pub speeds: Vec<String>,
+    // Initial clock time in seconds
+    pub min_base: Option<u64>,
+    pub max_base: Option<u64>,
+    pub max_increment: Option<u64>,
+    pub rated: bool,
+    pub casual: bool,
+    pub bots: bool,
+    pub humans: bool,
+    pub min_rating: Option<u32>,
+    pub max_rating: Option<u32>,
+    // Lowercase user ids, when non-empty nobody else gets a game
+    pub allow_list: Vec<String>,
+    pub block_list: Vec<String>,
+}
+
+impl Default for ChallengePolicy {
+    fn default() -> Self {
+        ChallengePolicy {
+            variants: vec!["standard".to_string(), "fromPosition".to_string()],
+            speeds: ["bullet", "blitz", "rapid", "classical"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            min_base: None,
+            max_base: None,
+            max_increment: None,
+            rated: true,
+            casual: true,
+            bots: true,
+            humans: true,
+            min_rating: None,
+            max_rating: None,
+            allow_list: Vec::new(),
+            block_list: Vec::new(),
+        }
+    }
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+impl ChallengePolicy {
+    // Settings come from `lichess_challenge_*` variables, see the README
+    pub fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Self {
+        let mut policy = ChallengePolicy::default();
+        let number = |name: &str| var(name).and_then(|v| v.trim().parse().ok());
+        // Anything that is clearly neither yes nor no keeps the default
+        let flag = |name: &str, default: bool| match var(name) {
+            Some(value) => match value.trim().to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => true,
+                "false" | "no" | "off" | "0" => false,
+                _ => {
+                    println!("Ignoring {}={}, expected true or false", name, value);
+                    default
+                }
+            },
+            None => default,
+        };
+        if let Some(variants) = var("lichess_challenge_variants") {
+            policy.variants = list(&variants);
+        }
+        if let Some(speeds) = var("lichess_challenge_speeds") {
+            policy.speeds = list(&speeds);
+        }
+        policy.min_base = number("lichess_challenge_min_base");
+        policy.max_base = number("lichess_challenge_max_base");
+        policy.max_increment = number("lichess_challenge_max_increment");
+        policy.rated = flag("lichess_challenge_rated", policy.rated);
+        policy.casual = flag("lichess_challenge_casual", policy.casual);
+        policy.bots = flag("lichess_challenge_bots", policy.bots);
+        policy.humans = flag("lichess_challenge_humans", policy.humans);
+        policy.min_rating = number("lichess_challenge_min_rating").map(|r: u64| r as u32);
+        policy.max_rating = number("lichess_challenge_max_rating").map(|r: u64| r as u32);
+        let ids = |name: &str| {
+            var(name)
+                .map(|v| list(&v.to_lowercase()))
+                .unwrap_or_default()
+        };
+        policy.allow_list = ids("lichess_challenge_allow");
+        policy.block_list = ids("lichess_challenge_block");
+        policy
+    }
+
+    pub fn evaluate(&self, challenge: &Challenge) -> Result<(), DeclineReason> {
+        let challenger = challenge.challenger.as_ref();
+        let challenger_id = challenger.map(|c| c.id.to_lowercase()).unwrap_or_default();
+        if self.block_list.contains(&challenger_id)
+            || (!self.allow_list.is_empty() && !self.allow_list.contains(&challenger_id))
+        {
+            return Err(DeclineReason::Generic);
+        }
+
+        let variant = challenge
+            .variant
+            .as_ref()
+            .map(|v| v.key.as_str())
+            .unwrap_or("standard");
+        if !self.variants.iter().any(|v| v == variant) {
+            return Err(if variant == "standard" {
+                DeclineReason::Standard
+            } else {
+                DeclineReason::Variant
+            });
+        }
+
+        self.check_time_control(challenge)?;
+
+        if challenge.rated && !self.rated {
+            return Err(DeclineReason::Rated);
+        }
+        if !challenge.rated && !self.casual {
+            return Err(DeclineReason::Casual);
+        }
+
+        let is_bot = challenger.and_then(|c| c.title.as_deref()) == Some("BOT");
+        if is_bot && !self.bots {
+            return Err(DeclineReason::NoBot);
+        }
+        if !is_bot && !self.humans {
+            return Err(DeclineReason::OnlyBot);
+        }
+
+        if let Some(rating) = challenger.and_then(|c| c.rating) {
+            let too_low = self.min_rating.is_some_and(|min| rating < min);
+            let too_high = self.max_rating.is_some_and(|max| rating > max);
+            if too_low || too_high {
+                return Err(DeclineReason::Generic);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_time_control(&self, challenge: &Challenge) -> Result<(), DeclineReason> {
+        let speed = challenge.speed.as_deref().unwrap_or("correspondence");
+        if !self.speeds.iter().any(|s| s == speed) {
+            let rank = |s: &str| SPEEDS.iter().position(|known| *known == s);
+            let allowed = self
+                .speeds
+                .iter()
+                .filter_map(|s| rank(s))
+                .collect::<Vec<usize>>();
+            return Err(match rank(speed) {
+                Some(r) if allowed.iter().all(|a| r < *a) => DeclineReason::TooFast,
+                Some(r) if allowed.iter().all(|a| r > *a) => DeclineReason::TooSlow,
+                _ => DeclineReason::TimeControl,
+            });
+        }
+
+        let clock = challenge
+            .time_control
+            .as_ref()
+            .filter(|clock| clock.kind == "clock");
+        if let Some(clock) = clock {
+            let base = clock.limit.unwrap_or(0);
+            if self.min_base.is_some_and(|min| base < min) {
+                return Err(DeclineReason::TooFast);
+            }
+            if self.max_base.is_some_and(|max| base > max) {
+                return Err(DeclineReason::TooSlow);
+            }
+            let increment = clock.increment.unwrap_or(0);
+            if self.max_increment.is_some_and(|max| increment > max) {
+                return Err(DeclineReason::TimeControl);
+            }
+        }
+        Ok(())
+    }
+}
+
+// What to do with an acceptable challenge given the games already running
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Admission {
+    Accept,
+    Queue,
+}
+
+// Keeps the number of simultaneous games bounded. Accepted challenges hold
+// a slot straight away, Lichess reuses the challenge id for the game.
+#[derive(Debug)]
+pub struct GameSlots {
+    max_games: usize,
+    queue_size: usize,
+    games: HashSet<String>,
+    queue: VecDeque<String>,
+}
+
+impl GameSlots {
+    pub fn new(max_games: usize, queue_size: usize) -> Self {
+        GameSlots {
+            max_games,
+            queue_size,
+            games: HashSet::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    // `lichess_max_games` (default 1) and `lichess_challenge_queue` (default 0)
+    pub fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Self {
+        let number = |name: &str, default: usize| {
+            var(name)
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        GameSlots::new(
+            number("lichess_max_games", 1).max(1),
+            number("lichess_challenge_queue", 0),
+        )
+    }
+
+    pub fn admit(&mut self, challenge_id: &str) -> Result<Admission, DeclineReason> {
+        if self.games.len() < self.max_games {
+            self.games.insert(challenge_id.to_string());
+            Ok(Admission::Accept)
+        } else if self.queue.len() < self.queue_size {
+            self.queue.push_back(challenge_id.to_string());
+            Ok(Admission::Queue)
+        } else {
+            Err(DeclineReason::Later)
+        }
+    }
+
+    // Games can start without a challenge from us, they still take a slot
+    pub fn game_started(&mut self, game_id: &str) {
+        self.games.insert(game_id.to_string());
+    }
+
+    // Frees whatever `id` held (game, accepted or queued challenge) and
+    // returns the queued challenge that takes its place, if any
+    pub fn release(&mut self, id: &str) -> Option<String> {
+        self.games.remove(id);
+        self.queue.retain(|queued| queued != id);
+        if self.games.len() >= self.max_games {
+            return None;
+        }
+        let next = self.queue.pop_front()?;
+        self.games.insert(next.clone());
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn challenge(speed: &str, rated: bool, title: Option<&str>, rating: u32) -> Challenge {
+        let json = format!(
+            r#"{{"id":"c1","challenger":{{"id":"someone","name":"Someone","title":{},"rating":{}}},"variant":{{"key":"standard"}},"rated":{},"speed":"{}","timeControl":{{"type":"clock","limit":300,"increment":3}}}}"#,
+            title
+                .map(|t| format!("\"{}\"", t))
+                .unwrap_or_else(|| "null".to_string()),
+            rating,
+            rated,
+            speed
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn default_policy_accepts_a_normal_game() {
+        let policy = ChallengePolicy::default();
+        assert_eq!(
+            policy.evaluate(&challenge("blitz", true, None, 1500)),
+            Ok(())
+        );
+        assert_eq!(
+            policy.evaluate(&challenge("correspondence", false, Some("BOT"), 1500)),
+            Err(DeclineReason::TooSlow)
+        );
+    }
+
+    #[test]
+    fn declines_with_matching_reasons() {
+        let policy = ChallengePolicy {
+            speeds: vec!["blitz".to_string(), "rapid".to_string()],
+            rated: false,
+            bots: false,
+            max_rating: Some(2000),
+            ..ChallengePolicy::default()
+        };
+        let reason = |c: Challenge| policy.evaluate(&c).unwrap_err().key();
+        assert_eq!(reason(challenge("bullet", false, None, 1500)), "tooFast");
+        assert_eq!(reason(challenge("classical", false, None, 1500)), "tooSlow");
+        assert_eq!(reason(challenge("blitz", true, None, 1500)), "rated");
+        assert_eq!(
+            reason(challenge("blitz", false, Some("BOT"), 1500)),
+            "noBot"
+        );
+        assert_eq!(reason(challenge("blitz", false, None, 2100)), "generic");
+
+        let mut variant = challenge("blitz", false, None, 1500);
+        variant.variant.as_mut().unwrap().key = "atomic".to_string();
+        assert_eq!(reason(variant), "variant");
+    }
+
+    #[test]
+    fn checks_clock_limits() {
+        let policy = ChallengePolicy {
+            min_base: Some(600),
+            ..ChallengePolicy::default()
+        };
+        assert_eq!(
+            policy.evaluate(&challenge("blitz", true, None, 1500)),
+            Err(DeclineReason::TooFast)
+        );
+        let policy = ChallengePolicy {
+            max_increment: Some(2),
+            ..ChallengePolicy::default()
+        };
+        assert_eq!(
+            policy.evaluate(&challenge("blitz", true, None, 1500)),
+            Err(DeclineReason::TimeControl)
+        );
+    }
+
+    #[test]
+    fn reads_settings_and_lists_from_variables() {
+        let policy = ChallengePolicy::from_vars(|name| match name {
+            "lichess_challenge_humans" => Some("false".to_string()),
+            "lichess_challenge_rated" => Some("True".to_string()),
+            "lichess_challenge_casual" => Some("On".to_string()),
+            "lichess_challenge_bots" => Some("maybe".to_string()),
+            "lichess_challenge_block" => Some("Someone, troll".to_string()),
+            "lichess_challenge_speeds" => Some("blitz".to_string()),
+            _ => None,
+        });
+        assert!(!policy.humans);
+        assert!(policy.rated && policy.casual);
+        assert_eq!(policy.bots, ChallengePolicy::default().bots);
+        assert_eq!(policy.block_list, vec!["someone", "troll"]);
+        assert_eq!(
+            policy.evaluate(&challenge("blitz", true, None, 1500)),
+            Err(DeclineReason::Generic)
+        );
+        let allowed = ChallengePolicy {
+            humans: false,
+            ..ChallengePolicy::default()
+        };
+        assert_eq!(
+            allowed.evaluate(&challenge("blitz", true, None, 1500)),
+            Err(DeclineReason::OnlyBot)
+        );
+    }
+
+    #[test]
+    fn queues_challenges_until_a_game_finishes() {
+        let mut slots = GameSlots::new(1, 1);
+        assert_eq!(slots.admit("a"), Ok(Admission::Accept));
+        assert_eq!(slots.admit("b"), Ok(Admission::Queue));
+        assert_eq!(slots.admit("c"), Err(DeclineReason::Later));
+        slots.game_started("a");
+        assert_eq!(slots.release("a").as_deref(), Some("b"));
+        assert_eq!(slots.admit("d"), Ok(Admission::Queue));
+        // A withdrawn challenge gives up its place in the queue
+        assert_eq!(slots.release("d"), None);
+        assert_eq!(slots.release("b"), None);
+        assert_eq!(slots.admit("e"), Ok(Admission::Accept));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::engine::SearchInfo;
+use crate::lichess::ChatLine;
+use std::time::Duration;
+use std::time::Instant;
+
+// Shortest gap between two command replies in one game
+const REPLY_INTERVAL: Duration = Duration::from_secs(5);
+const HELP: &str = "Commands: !eval, !depth, !name, !help";
+
+// What the bot says unprompted, None keeps it quiet
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatConfig {
+    pub greeting: Option<String>,
+    pub goodbye: Option<String>,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        ChatConfig {
+            greeting: Some("Hi! Good luck, type !help to see what I answer to.".to_string()),
+            goodbye: Some("Good game, thanks for playing!".to_string()),
+        }
+    }
+}
+
+impl ChatConfig {
+    // `lichess_chat_greeting` and `lichess_chat_goodbye`, set empty to disable
+    pub fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Self {
+        let defaults = ChatConfig::default();
+        let message = |name: &str, default: Option<String>| match var(name) {
+            Some(text) if text.trim().is_empty() => None,
+            Some(text) => Some(text),
+            None => default,
+        };
+        ChatConfig {
+            greeting: message("lichess_chat_greeting", defaults.greeting),
+            goodbye: message("lichess_chat_goodbye", defaults.goodbye),
+        }
+    }
+}
+
+// Command handling for the chat of one game
+pub struct Chat {
+    bot_id: String,
+    last_reply: Option<Instant>,
+}
+
+impl Chat {
+    pub fn new(bot_id: &str) -> Self {
+        Chat {
+            bot_id: bot_id.to_string(),
+            last_reply: None,
+        }
+    }
+
+    // The answer to a command, None for ordinary chat, the bot's own lines
+    // and commands arriving faster than REPLY_INTERVAL
+    pub fn reply(
+        &mut self,
+        line: &ChatLine,
+        engine_name: &str,
+        search: Option<&SearchInfo>,
+        now: Instant,
+    ) -> Option<String> {
+        if line.username.eq_ignore_ascii_case(&self.bot_id) {
+            return None;
+        }
+        let command = line.text.split_whitespace().next()?.strip_prefix('!')?;
+        let answer = match command.to_lowercase().as_str() {
+            "eval" => match search {
+                Some(info) => format!("Evaluation {} at depth {}", info.score, info.depth),
+                None => "No evaluation yet".to_string(),
+            },
+            "depth" => match search {
+                Some(info) => format!("Searched {} plies deep, {} nodes", info.depth, info.nodes),
+                None => "No search yet".to_string(),
+            },
+            "name" => format!(
+                "{} {} playing with the {} engine",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+                engine_name
+            ),
+            "help" => HELP.to_string(),
+            _ => return None,
+        };
+        if self
+            .last_reply
+            .is_some_and(|last| now.duration_since(last) < REPLY_INTERVAL)
+        {
+            return None;
+        }
+        self.last_reply = Some(now);
+        Some(answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Score;
+
+    fn line(username: &str, text: &str) -> ChatLine {
+        ChatLine {
+            username: username.to_string(),
+            text: text.to_string(),
+            room: "player".to_string(),
+        }
+    }
+
+    #[test]
+    fn answers_commands_at_a_limited_rate() {
+        let mut chat = Chat::new("gambinobot");
+        let info = SearchInfo {
+            depth: 9,
+            score: Score::Centipawns(-120),
+            nodes: 5000,
+        };
+        let now = Instant::now();
+        assert_eq!(
+            chat.reply(
+                &line("Someone", "!EVAL please"),
+                "built-in",
+                Some(&info),
+                now
+            )
+            .as_deref(),
+            Some("Evaluation -1.20 at depth 9")
+        );
+        assert_eq!(
+            chat.reply(&line("Someone", "!help"), "built-in", None, now),
+            None
+        );
+        let later = now + REPLY_INTERVAL;
+        assert_eq!(
+            chat.reply(&line("Someone", "!depth"), "built-in", None, later)
+                .as_deref(),
+            Some("No search yet")
+        );
+        let much_later = later + REPLY_INTERVAL;
+        assert_eq!(
+            chat.reply(&line("Someone", "good luck"), "built-in", None, much_later),
+            None
+        );
+        assert_eq!(
+            chat.reply(&line("GambinoBot", "!help"), "built-in", None, much_later),
+            None
+        );
+        assert!(chat
+            .reply(&line("Someone", "!name"), "Stockfish 16", None, much_later)
+            .unwrap()
+            .ends_with("playing with the Stockfish 16 engine"));
+    }
+
+    #[test]
+    fn empty_messages_turn_greetings_off() {
+        let config = ChatConfig::from_vars(|name| match name {
+            "lichess_chat_greeting" => Some("Hello".to_string()),
+            "lichess_chat_goodbye" => Some(" ".to_string()),
+            _ => None,
+        });
+        assert_eq!(config.greeting.as_deref(), Some("Hello"));
+        assert_eq!(config.goodbye, None);
+    }
+}
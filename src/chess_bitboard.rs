@@ -1,3 +1,20 @@
+pub use self::book::BookSelection;
+pub use self::book::OpeningBook;
+pub use self::clock::allocate_time;
+pub use self::evaluation::evaluate;
 pub use self::game::ChessEngine;
-pub use self::game::FromString;
+pub use self::search::SearchResult;
+pub use self::search::Searcher;
+pub use self::search::MAX_DEPTH;
+pub use self::tablebase::Tablebase;
+mod book;
+mod book_keys;
+mod clock;
+mod draw;
+mod evaluation;
 mod game;
+mod ordering;
+mod pawns;
+mod search;
+mod tablebase;
+mod transposition;
@@ -0,0 +1,253 @@
+use super::book_keys::RANDOM64;
+use super::ChessEngine;
+use chess::Board;
+use chess::CastleRights;
+use chess::ChessMove;
+use chess::Color;
+use chess::File;
+use chess::Piece;
+use chess::Rank;
+use chess::Square;
+use chess::ALL_PIECES;
+use std::fs;
+
+const ENTRY_SIZE: usize = 16;
+const CASTLE_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BookSelection {
+    WeightedRandom,
+    BestWeight,
+}
+
+impl BookSelection {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "random" => Some(BookSelection::WeightedRandom),
+            "best" => Some(BookSelection::BestWeight),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct BookEntry {
+    key: u64,
+    raw_move: u16,
+    weight: u16,
+}
+
+// Polyglot .bin book, entries are sorted by key so lookups are a binary search
+pub struct OpeningBook {
+    entries: Vec<BookEntry>,
+    selection: BookSelection,
+    // Number of plies into the game after which the book is no longer used
+    max_depth: usize,
+}
+
+impl OpeningBook {
+    pub fn open(path: &str, selection: BookSelection, max_depth: usize) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(OpeningBook::from_bytes(&bytes, selection, max_depth))
+    }
+
+    pub fn from_bytes(bytes: &[u8], selection: BookSelection, max_depth: usize) -> Self {
+        let entries = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| {
+                let mut key = [0; 8];
+                key.copy_from_slice(&chunk[0..8]);
+                BookEntry {
+                    key: u64::from_be_bytes(key),
+                    raw_move: u16::from_be_bytes([chunk[8], chunk[9]]),
+                    weight: u16::from_be_bytes([chunk[10], chunk[11]]),
+                }
+            })
+            .collect();
+        OpeningBook {
+            entries,
+            selection,
+            max_depth,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn find_move(&self, engine: &ChessEngine) -> Option<ChessMove> {
+        if engine.history.len() >= self.max_depth {
+            return None;
+        }
+        let board = &engine.bitboard;
+        let key = polyglot_key(board);
+        let start = self.entries.partition_point(|e| e.key < key);
+        let candidates = self.entries[start..]
+            .iter()
+            .take_while(|e| e.key == key)
+            .filter_map(|e| {
+                decode_move(board, e.raw_move)
+                    .filter(|m| board.legal(*m))
+                    .map(|m| (m, e.weight as u32))
+            })
+            .collect::<Vec<(ChessMove, u32)>>();
+        if candidates.is_empty() {
+            return None;
+        }
+        match self.selection {
+            BookSelection::BestWeight => candidates.iter().max_by_key(|c| c.1).map(|c| c.0),
+            BookSelection::WeightedRandom => {
+                let total: u32 = candidates.iter().map(|c| c.1).sum();
+                if total == 0 {
+                    return Some(candidates[0].0);
+                }
+                let mut pick = rand::random::<u32>() % total;
+                for (m, weight) in candidates.iter() {
+                    if pick < *weight {
+                        return Some(*m);
+                    }
+                    pick -= weight;
+                }
+                None
+            }
+        }
+    }
+}
+
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for piece in ALL_PIECES.iter() {
+        for color in [Color::White, Color::Black].iter() {
+            let kind = 2 * piece.to_index() + (*color == Color::White) as usize;
+            for sq in board.pieces(*piece) & board.color_combined(*color) {
+                key ^= RANDOM64[64 * kind + sq.to_index()];
+            }
+        }
+    }
+
+    for (offset, color) in [(0, Color::White), (2, Color::Black)].iter() {
+        let rights = board.castle_rights(*color);
+        if rights.has_kingside() {
+            key ^= RANDOM64[CASTLE_OFFSET + offset];
+        }
+        if rights.has_queenside() {
+            key ^= RANDOM64[CASTLE_OFFSET + offset + 1];
+        }
+    }
+
+    // The chess crate only records en passant when a capture is possible,
+    // which is exactly when Polyglot hashes it
+    if let Some(sq) = board.en_passant() {
+        key ^= RANDOM64[EN_PASSANT_OFFSET + sq.get_file().to_index()];
+    }
+
+    if board.side_to_move() == Color::White {
+        key ^= RANDOM64[TURN_OFFSET];
+    }
+    key
+}
+
+fn decode_move(board: &Board, raw: u16) -> Option<ChessMove> {
+    let square = |file: u16, rank: u16| {
+        Square::make_square(
+            Rank::from_index(rank as usize),
+            File::from_index(file as usize),
+        )
+    };
+    let source = square((raw >> 6) & 7, (raw >> 9) & 7);
+    let mut dest = square(raw & 7, (raw >> 3) & 7);
+    let promotion = match (raw >> 12) & 7 {
+        0 => None,
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => return None,
+    };
+    // Castling is stored as the king capturing its own rook
+    if board.piece_on(source) == Some(Piece::King)
+        && board.color_on(dest) == Some(board.side_to_move())
+        && board.my_castle_rights() != CastleRights::NoRights
+    {
+        let file = if dest.get_file() == File::H {
+            File::G
+        } else {
+            File::C
+        };
+        dest = Square::make_square(dest.get_rank(), file);
+    }
+    Some(ChessMove::new(source, dest, promotion))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(fen: &str) -> u64 {
+        polyglot_key(&ChessEngine::from_fen(fen.to_string()).bitboard)
+    }
+
+    fn entry(key: u64, raw_move: u16, weight: u16) -> Vec<u8> {
+        let mut bytes = key.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&raw_move.to_be_bytes());
+        bytes.extend_from_slice(&weight.to_be_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    #[test]
+    fn matches_polyglot_reference_keys() {
+        let positions = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                0x463b_9618_1691_fc9c,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                0x823c_9b50_fd11_4196,
+            ),
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+                0x22a4_8b5a_8e47_ff78,
+            ),
+            (
+                "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 2 4",
+                0x00fd_d303_c946_bdd9,
+            ),
+            (
+                "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+                0x3c81_23ea_7b06_7637,
+            ),
+        ];
+        for (fen, expected) in positions.iter() {
+            assert_eq!(key(fen), *expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn picks_best_weighted_move() {
+        let start = key("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        // e2e4 and d2d4
+        let mut bytes = entry(start, (4 << 6) | (1 << 9) | 4 | (3 << 3), 10);
+        bytes.extend(entry(start, (3 << 6) | (1 << 9) | 3 | (3 << 3), 30));
+        let book = OpeningBook::from_bytes(&bytes, BookSelection::BestWeight, 10);
+        assert_eq!(book.len(), 2);
+        let m = book.find_move(&ChessEngine::default()).unwrap();
+        assert_eq!(m.to_string(), "d2d4");
+    }
+
+    #[test]
+    fn respects_max_depth_and_castling_encoding() {
+        let engine = ChessEngine::from_fen(
+            "r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4".to_string(),
+        );
+        // e1h1 in Polyglot notation
+        let bytes = entry(polyglot_key(&engine.bitboard), (4 << 6) | 7, 1);
+        let book = OpeningBook::from_bytes(&bytes, BookSelection::WeightedRandom, 10);
+        assert_eq!(book.find_move(&engine).unwrap().to_string(), "e1g1");
+        let shallow = OpeningBook::from_bytes(&bytes, BookSelection::WeightedRandom, 0);
+        assert!(shallow.find_move(&engine).is_none());
+    }
+}
@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+// Expected number of moves still to be played when splitting the remaining clock
+const MOVES_TO_GO: u32 = 30;
+// Reserved for network lag between Lichess and the bot
+const MOVE_OVERHEAD: Duration = Duration::from_millis(150);
+const MIN_MOVE_TIME: Duration = Duration::from_millis(20);
+const MAX_MOVE_TIME: Duration = Duration::from_secs(30);
+
+pub fn allocate_time(remaining: Duration, increment: Duration) -> Duration {
+    let usable = remaining.saturating_sub(MOVE_OVERHEAD);
+    let budget = usable / MOVES_TO_GO + increment * 3 / 4;
+    budget.min(usable / 2).min(MAX_MOVE_TIME).max(MIN_MOVE_TIME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_share_of_clock_plus_increment() {
+        let budget = allocate_time(Duration::from_secs(60), Duration::from_secs(2));
+        assert!(budget > Duration::from_millis(3000));
+        assert!(budget < Duration::from_millis(4000));
+    }
+
+    #[test]
+    fn never_exceeds_half_of_remaining_time() {
+        let budget = allocate_time(Duration::from_millis(400), Duration::from_secs(5));
+        assert!(budget <= Duration::from_millis(125));
+        assert!(budget >= MIN_MOVE_TIME);
+    }
+}
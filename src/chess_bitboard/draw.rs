@@ -0,0 +1,50 @@
+use chess::Board;
+use chess::ChessMove;
+use chess::Piece;
+
+const LIGHT_SQUARES: u64 = 0x55AA_55AA_55AA_55AA;
+
+// Positions where neither side can possibly deliver mate
+pub fn insufficient_material(board: &Board) -> bool {
+    let heavy = board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    if heavy.popcnt() > 0 {
+        return false;
+    }
+    let knights = board.pieces(Piece::Knight).popcnt();
+    let bishops = board.pieces(Piece::Bishop).0;
+    if knights + bishops.count_ones() <= 1 {
+        return true;
+    }
+    // Any number of bishops that all share a square colour
+    knights == 0 && (bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0)
+}
+
+// Pawn moves and captures reset the fifty-move counter
+pub fn resets_halfmove_clock(board: &Board, m: ChessMove) -> bool {
+    board.piece_on(m.get_source()) == Some(Piece::Pawn) || board.piece_on(m.get_dest()).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn insufficient(fen: &str) -> bool {
+        insufficient_material(&Board::from_str(fen).unwrap())
+    }
+
+    #[test]
+    fn recognises_dead_positions() {
+        assert!(insufficient("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/4KN2 w - - 0 1"));
+        assert!(insufficient("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn mating_material_is_sufficient() {
+        assert!(!insufficient("4k3/8/8/8/8/8/8/4KR2 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/P7/4K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"));
+        assert!(!insufficient("4k1n1/8/8/8/8/8/8/4KB2 w - - 0 1"));
+    }
+}
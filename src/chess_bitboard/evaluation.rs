@@ -0,0 +1,366 @@
+use super::pawns::file_mask;
+use super::pawns::relative_rank;
+use super::pawns::PawnTable;
+use super::pawns::PASSED_EG;
+use super::ChessEngine;
+use chess::get_bishop_moves;
+use chess::get_king_moves;
+use chess::get_knight_moves;
+use chess::get_rook_moves;
+use chess::BitBoard;
+use chess::Board;
+use chess::Color;
+use chess::Piece;
+use chess::Square;
+use chess::ALL_PIECES;
+
+// PeSTO material and piece-square values, tables are laid out from White's
+// point of view with a8 first so they read like a board diagram
+const MG_VALUE: [i32; 6] = [82, 337, 365, 477, 1025, 0];
+const EG_VALUE: [i32; 6] = [94, 281, 297, 512, 936, 0];
+
+// Game phase contribution per piece, a full set of pieces adds up to 24
+const PHASE_INC: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+// Pawn shield bonus for a pawn one or two ranks in front of the king, and the
+// penalty for a shield file with no pawn at all
+const SHIELD: [i32; 2] = [12, 6];
+const SHIELD_MISSING: i32 = -15;
+// Weight of each piece type attacking the king zone, indexed like ALL_PIECES
+const ATTACK_WEIGHT: [i32; 6] = [0, 20, 20, 40, 80, 0];
+// Percentage of the attack weight that counts, by number of attackers
+const ATTACKER_SCALE: [i32; 8] = [0, 0, 50, 75, 88, 94, 97, 99];
+
+#[rustfmt::skip]
+const MG_PAWN: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     98, 134,  61,  95,  68, 126,  34, -11,
+     -6,   7,  26,  31,  65,  56,  25, -20,
+    -14,  13,   6,  21,  23,  12,  17, -23,
+    -27,  -2,  -5,  12,  17,   6,  10, -25,
+    -26,  -4,  -4, -10,   3,   3,  33, -12,
+    -35,  -1, -20, -23, -15,  24,  38, -22,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const EG_PAWN: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+    178, 173, 158, 134, 147, 132, 165, 187,
+     94, 100,  85,  67,  56,  53,  82,  84,
+     32,  24,  13,   5,  -2,   4,  17,  17,
+     13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+      4,   7,  -6,   1,   0,  -5,  -1,  -8,
+     13,   8,   8,  10,  13,   0,   2,  -7,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const MG_KNIGHT: [i32; 64] = [
+    -167, -89, -34, -49,  61, -97, -15, -107,
+     -73, -41,  72,  36,  23,  62,   7,  -17,
+     -47,  60,  37,  65,  84, 129,  73,   44,
+      -9,  17,  19,  53,  37,  69,  18,   22,
+     -13,   4,  16,  13,  28,  19,  21,   -8,
+     -23,  -9,  12,  10,  19,  17,  25,  -16,
+     -29, -53, -12,  -3,  -1,  18, -14,  -19,
+    -105, -21, -58, -33, -17, -28, -19,  -23,
+];
+
+#[rustfmt::skip]
+const EG_KNIGHT: [i32; 64] = [
+    -58, -38, -13, -28, -31, -27, -63, -99,
+    -25,  -8, -25,  -2,  -9, -25, -24, -52,
+    -24, -20,  10,   9,  -1,  -9, -19, -41,
+    -17,   3,  22,  22,  22,  11,   8, -18,
+    -18,  -6,  16,  25,  16,  17,   4, -18,
+    -23,  -3,  -1,  15,  10,  -3, -20, -22,
+    -42, -20, -10,  -5,  -2, -20, -23, -44,
+    -29, -51, -23, -15, -22, -18, -50, -64,
+];
+
+#[rustfmt::skip]
+const MG_BISHOP: [i32; 64] = [
+    -29,   4, -82, -37, -25, -42,   7,  -8,
+    -26,  16, -18, -13,  30,  59,  18, -47,
+    -16,  37,  43,  40,  35,  50,  37,  -2,
+     -4,   5,  19,  50,  37,  37,   7,  -2,
+     -6,  13,  13,  26,  34,  12,  10,   4,
+      0,  15,  15,  15,  14,  27,  18,  10,
+      4,  15,  16,   0,   7,  21,  33,   1,
+    -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+
+#[rustfmt::skip]
+const EG_BISHOP: [i32; 64] = [
+    -14, -21, -11,  -8,  -7,  -9, -17, -24,
+     -8,  -4,   7, -12,  -3, -13,  -4, -14,
+      2,  -8,   0,  -1,  -2,   6,   0,   4,
+     -3,   9,  12,   9,  14,  10,   3,   2,
+     -6,   3,  13,  19,   7,  10,  -3,  -9,
+    -12,  -3,   8,  10,  13,   3,  -7, -15,
+    -14, -18,  -7,  -1,   4,  -9, -15, -27,
+    -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+];
+
+#[rustfmt::skip]
+const MG_ROOK: [i32; 64] = [
+     32,  42,  32,  51,  63,   9,  31,  43,
+     27,  32,  58,  62,  80,  67,  26,  44,
+     -5,  19,  26,  36,  17,  45,  61,  16,
+    -24, -11,   7,  26,  24,  35,  -8, -20,
+    -36, -26, -12,  -1,   9,  -7,   6, -23,
+    -45, -25, -16, -17,   3,   0,  -5, -33,
+    -44, -16, -20,  -9,  -1,  11,  -6, -71,
+    -19, -13,   1,  17,  16,   7, -37, -26,
+];
+
+#[rustfmt::skip]
+const EG_ROOK: [i32; 64] = [
+     13,  10,  18,  15,  12,  12,   8,   5,
+     11,  13,  13,  11,  -3,   3,   8,   3,
+      7,   7,   7,   5,   4,  -3,  -5,  -3,
+      4,   3,  13,   1,   2,   1,  -1,   2,
+      3,   5,   8,   4,  -5,  -6,  -8, -11,
+     -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+     -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+     -9,   2,   3,  -1,  -5, -13,   4, -20,
+];
+
+#[rustfmt::skip]
+const MG_QUEEN: [i32; 64] = [
+    -28,   0,  29,  12,  59,  44,  43,  45,
+    -24, -39,  -5,   1, -16,  57,  28,  54,
+    -13, -17,   7,   8,  29,  56,  47,  57,
+    -27, -27, -16, -16,  -1,  17,  -2,   1,
+     -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+    -14,   2, -11,  -2,  -5,   2,  14,   5,
+    -35,  -8,  11,   2,   8,  15,  -3,   1,
+     -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+
+#[rustfmt::skip]
+const EG_QUEEN: [i32; 64] = [
+     -9,  22,  22,  27,  27,  19,  10,  20,
+    -17,  20,  32,  41,  58,  25,  30,   0,
+    -20,   6,   9,  49,  47,  35,  19,   9,
+      3,  22,  24,  45,  57,  40,  57,  36,
+    -18,  28,  19,  47,  31,  34,  39,  23,
+    -16, -27,  15,   6,   9,  17,  10,   5,
+    -22, -23, -30, -16, -16, -23, -36, -32,
+    -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+
+#[rustfmt::skip]
+const MG_KING: [i32; 64] = [
+    -65,  23,  16, -15, -56, -34,   2,  13,
+     29,  -1, -20,  -7,  -8,  -4, -38, -29,
+     -9,  24,   2, -16, -20,   6,  22, -22,
+    -17, -20, -12, -27, -30, -25, -14, -36,
+    -49,  -1, -27, -39, -46, -44, -33, -51,
+    -14, -14, -22, -46, -44, -30, -15, -27,
+      1,   7,  -8, -64, -43, -16,   9,   8,
+    -15,  36,  12, -54,   8, -28,  24,  14,
+];
+
+#[rustfmt::skip]
+const EG_KING: [i32; 64] = [
+    -74, -35, -18, -18, -11,  15,   4, -17,
+    -12,  17,  14,  17,  17,  38,  23,  11,
+     10,  17,  23,  15,  20,  45,  44,  13,
+     -8,  22,  24,  27,  26,  33,  26,   3,
+    -18,  -4,  21,  24,  27,  23,   9, -11,
+    -19,  -3,  11,  21,  23,  16,   7,  -9,
+    -27, -11,   4,  13,  14,   4,  -5, -17,
+    -53, -34, -21, -11, -28, -14, -24, -43,
+];
+
+const MG_TABLES: [&[i32; 64]; 6] = [
+    &MG_PAWN, &MG_KNIGHT, &MG_BISHOP, &MG_ROOK, &MG_QUEEN, &MG_KING,
+];
+const EG_TABLES: [&[i32; 64]; 6] = [
+    &EG_PAWN, &EG_KNIGHT, &EG_BISHOP, &EG_ROOK, &EG_QUEEN, &EG_KING,
+];
+
+pub fn evaluate(engine: &ChessEngine) -> i32 {
+    evaluate_board(&engine.bitboard, &mut PawnTable::new(1))
+}
+
+// Centipawn score from the side to move's perspective
+pub fn evaluate_board(board: &Board, pawn_table: &mut PawnTable) -> i32 {
+    let mut mg = [0; 2];
+    let mut eg = [0; 2];
+    let mut phase = 0;
+
+    for piece in ALL_PIECES.iter() {
+        let p = piece.to_index();
+        for color in [Color::White, Color::Black].iter() {
+            let c = color.to_index();
+            for sq in board.pieces(*piece) & board.color_combined(*color) {
+                let index = table_index(sq.to_index(), *color);
+                mg[c] += MG_VALUE[p] + MG_TABLES[p][index];
+                eg[c] += EG_VALUE[p] + EG_TABLES[p][index];
+                phase += PHASE_INC[p];
+            }
+        }
+    }
+
+    let pawns = pawn_table.probe(board);
+    mg[Color::White.to_index()] += pawns.mg;
+    eg[Color::White.to_index()] += pawns.eg;
+    for color in [Color::White, Color::Black].iter() {
+        let c = color.to_index();
+        eg[c] += blocked_passer_penalty(board, *color, pawns.passed[c]);
+        mg[c] += king_shield(board, *color) + king_attack_penalty(board, *color);
+    }
+
+    let us = board.side_to_move().to_index();
+    let them = 1 - us;
+    let mg_score = mg[us] - mg[them];
+    let eg_score = eg[us] - eg[them];
+    // Early promotions can push the phase above the starting total
+    let mg_phase = phase.min(MAX_PHASE);
+    (mg_score * mg_phase + eg_score * (MAX_PHASE - mg_phase)) / MAX_PHASE
+}
+
+// A passed pawn with a piece on its stop square is worth half as much
+fn blocked_passer_penalty(board: &Board, color: Color, passed: u64) -> i32 {
+    let mut penalty = 0;
+    for sq in BitBoard(passed) {
+        let stop = match color {
+            Color::White => sq.up(),
+            Color::Black => sq.down(),
+        };
+        if let Some(stop) = stop {
+            if board.piece_on(stop).is_some() {
+                penalty -= PASSED_EG[relative_rank(color, sq.get_rank().to_index())] / 2;
+            }
+        }
+    }
+    penalty
+}
+
+fn king_shield(board: &Board, color: Color) -> i32 {
+    let king = board.king_square(color);
+    let (file, rank) = (king.get_file().to_index(), king.get_rank().to_index());
+    // Only a castled or home king relies on its pawn shield
+    if relative_rank(color, rank) > 1 {
+        return 0;
+    }
+    let own_pawns = (board.pieces(Piece::Pawn) & board.color_combined(color)).0;
+    let mut score = 0;
+    for f in file.saturating_sub(1)..=(file + 1).min(7) {
+        let mut covered = false;
+        for (distance, bonus) in SHIELD.iter().enumerate() {
+            let shield_rank = match color {
+                Color::White => rank + 1 + distance,
+                Color::Black => rank.wrapping_sub(1 + distance),
+            };
+            if shield_rank < 8 && own_pawns & file_mask(f) & (0xFF << (8 * shield_rank)) != 0 {
+                score += bonus;
+                covered = true;
+                break;
+            }
+        }
+        if !covered {
+            score += SHIELD_MISSING;
+        }
+    }
+    score
+}
+
+fn king_attack_penalty(board: &Board, color: Color) -> i32 {
+    let king = board.king_square(color);
+    let zone = get_king_moves(king) | BitBoard::from_square(king);
+    let occupied = *board.combined();
+    let mut attackers = 0;
+    let mut weight = 0;
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen].iter() {
+        for sq in board.pieces(*piece) & board.color_combined(!color) {
+            if attacks(*piece, sq, occupied) & zone != BitBoard(0) {
+                attackers += 1;
+                weight += ATTACK_WEIGHT[piece.to_index()];
+            }
+        }
+    }
+    -weight * ATTACKER_SCALE[attackers.min(7)] / 100
+}
+
+fn attacks(piece: Piece, sq: Square, occupied: BitBoard) -> BitBoard {
+    match piece {
+        Piece::Knight => get_knight_moves(sq),
+        Piece::Bishop => get_bishop_moves(sq, occupied),
+        Piece::Rook => get_rook_moves(sq, occupied),
+        Piece::Queen => get_bishop_moves(sq, occupied) | get_rook_moves(sq, occupied),
+        _ => BitBoard(0),
+    }
+}
+
+// Tables start at a8, the chess crate numbers squares from a1
+fn table_index(square: usize, color: Color) -> usize {
+    match color {
+        Color::White => square ^ 56,
+        Color::Black => square,
+    }
+}
+
+pub fn piece_value(piece: Piece) -> i32 {
+    MG_VALUE[piece.to_index()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_position_is_balanced() {
+        assert_eq!(evaluate(&ChessEngine::default()), 0);
+    }
+
+    #[test]
+    fn score_is_from_side_to_move() {
+        let white = ChessEngine::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1".to_string());
+        let black = ChessEngine::from_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1".to_string());
+        assert!(evaluate(&white) > 800);
+        assert_eq!(evaluate(&white), -evaluate(&black));
+    }
+
+    #[test]
+    fn pawn_shield_is_rewarded() {
+        let sheltered = ChessEngine::from_fen(
+            "r1bq1rk1/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQ1RK1 w - - 0 1".to_string(),
+        );
+        let exposed = ChessEngine::from_fen(
+            "r1bq1rk1/pppp1ppp/2n2n2/4p3/4P3/2N2NPP/PPPP1P2/R1BQ1RK1 w - - 0 1".to_string(),
+        );
+        assert!(king_shield(&sheltered.bitboard, Color::White) > 0);
+        assert!(
+            king_shield(&sheltered.bitboard, Color::White)
+                > king_shield(&exposed.bitboard, Color::White)
+        );
+    }
+
+    #[test]
+    fn king_zone_attackers_are_penalised() {
+        let board = ChessEngine::from_fen("6k1/5ppp/8/6N1/8/3B4/1Q6/6K1 b - - 0 1".to_string());
+        assert!(king_attack_penalty(&board.bitboard, Color::Black) < 0);
+        assert_eq!(king_attack_penalty(&board.bitboard, Color::White), 0);
+    }
+
+    #[test]
+    fn blocked_passed_pawn_is_worth_less() {
+        let free = ChessEngine::from_fen("4k3/8/8/8/3P4/8/8/4K3 w - - 0 1".to_string());
+        let blocked = ChessEngine::from_fen("4k3/8/8/3n4/3P4/8/8/4K3 w - - 0 1".to_string());
+        let free_entry = PawnTable::new(1).probe(&free.bitboard);
+        let penalty = blocked_passer_penalty(&blocked.bitboard, Color::White, free_entry.passed[0]);
+        assert!(penalty < 0);
+    }
+
+    #[test]
+    fn mirrored_positions_score_the_same() {
+        let white = ChessEngine::from_fen("4k3/8/8/8/8/5N2/8/4K3 w - - 0 1".to_string());
+        let black = ChessEngine::from_fen("4k3/8/5n2/8/8/8/8/4K3 b - - 0 1".to_string());
+        assert_eq!(evaluate(&white), evaluate(&black));
+    }
+}
@@ -0,0 +1,180 @@
+use super::search::MAX_PLY;
+use chess::Board;
+use chess::ChessMove;
+use chess::Color;
+use chess::Piece;
+
+const TT_MOVE_SCORE: i32 = 1_000_000;
+const CAPTURE_SCORE: i32 = 100_000;
+const PROMOTION_SCORE: i32 = 95_000;
+const KILLER_SCORES: [i32; 2] = [90_000, 80_000];
+// History scores are halved once any entry reaches this value
+const HISTORY_LIMIT: i32 = 50_000;
+
+pub struct MoveOrdering {
+    killers: Vec<[Option<ChessMove>; 2]>,
+    history: Box<[[[i32; 64]; 64]; 2]>,
+}
+
+impl MoveOrdering {
+    pub fn new() -> Self {
+        MoveOrdering {
+            killers: vec![[None; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for slot in self.killers.iter_mut() {
+            *slot = [None; 2];
+        }
+        *self.history = [[[0; 64]; 64]; 2];
+    }
+
+    // Killers only apply to the position they were found in, history carries
+    // over between moves of the same game at reduced weight
+    pub fn new_search(&mut self) {
+        for slot in self.killers.iter_mut() {
+            *slot = [None; 2];
+        }
+        self.age_history();
+    }
+
+    pub fn order_moves(
+        &self,
+        board: &Board,
+        moves: &mut [ChessMove],
+        tt_move: Option<ChessMove>,
+        ply: usize,
+    ) {
+        moves.sort_by_cached_key(|m| -self.score_move(board, *m, tt_move, ply));
+    }
+
+    pub fn order_captures(board: &Board, moves: &mut [ChessMove]) {
+        moves.sort_by_cached_key(|m| -mvv_lva(board, *m));
+    }
+
+    fn score_move(
+        &self,
+        board: &Board,
+        m: ChessMove,
+        tt_move: Option<ChessMove>,
+        ply: usize,
+    ) -> i32 {
+        if Some(m) == tt_move {
+            return TT_MOVE_SCORE;
+        }
+        if is_capture(board, m) {
+            return CAPTURE_SCORE + mvv_lva(board, m);
+        }
+        if m.get_promotion() == Some(Piece::Queen) {
+            return PROMOTION_SCORE;
+        }
+        if let Some(killers) = self.killers.get(ply) {
+            for (slot, killer) in killers.iter().enumerate() {
+                if *killer == Some(m) {
+                    return KILLER_SCORES[slot];
+                }
+            }
+        }
+        self.history_score(board.side_to_move(), m)
+    }
+
+    pub fn is_quiet(board: &Board, m: ChessMove) -> bool {
+        !is_capture(board, m) && m.get_promotion().is_none()
+    }
+
+    // Called on a beta cut-off caused by a quiet move
+    pub fn record_cutoff(&mut self, board: &Board, m: ChessMove, depth: isize, ply: usize) {
+        if let Some(killers) = self.killers.get_mut(ply) {
+            if killers[0] != Some(m) {
+                killers[1] = killers[0];
+                killers[0] = Some(m);
+            }
+        }
+        let side = board.side_to_move().to_index();
+        let entry = &mut self.history[side][m.get_source().to_index()][m.get_dest().to_index()];
+        *entry += (depth * depth) as i32;
+        if *entry >= HISTORY_LIMIT {
+            self.age_history();
+        }
+    }
+
+    fn history_score(&self, color: Color, m: ChessMove) -> i32 {
+        self.history[color.to_index()][m.get_source().to_index()][m.get_dest().to_index()]
+    }
+
+    fn age_history(&mut self) {
+        for side in self.history.iter_mut() {
+            for from in side.iter_mut() {
+                for score in from.iter_mut() {
+                    *score /= 2;
+                }
+            }
+        }
+    }
+}
+
+fn is_capture(board: &Board, m: ChessMove) -> bool {
+    board.piece_on(m.get_dest()).is_some()
+        || (board.piece_on(m.get_source()) == Some(Piece::Pawn)
+            && m.get_source().get_file() != m.get_dest().get_file())
+}
+
+// Most valuable victim first, least valuable attacker breaks ties
+fn mvv_lva(board: &Board, m: ChessMove) -> i32 {
+    let victim = match board.piece_on(m.get_dest()) {
+        Some(piece) => rank_value(piece),
+        // En passant and quiet promotions
+        None if is_capture(board, m) => rank_value(Piece::Pawn),
+        None => 0,
+    };
+    let attacker = board.piece_on(m.get_source()).map(rank_value).unwrap_or(0);
+    let promotion = m.get_promotion().map(rank_value).unwrap_or(0);
+    victim * 10 + promotion * 10 - attacker
+}
+
+fn rank_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 1,
+        Piece::Knight => 2,
+        Piece::Bishop => 3,
+        Piece::Rook => 4,
+        Piece::Queen => 5,
+        Piece::King => 6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::MoveGen;
+    use chess::Square;
+    use std::str::FromStr;
+
+    #[test]
+    fn orders_tt_move_then_captures_by_mvv_lva() {
+        let board = Board::from_str("4k3/8/8/3q1r2/4P3/8/8/3QK3 w - - 0 1").unwrap();
+        let mut moves = MoveGen::new_legal(&board).collect::<Vec<ChessMove>>();
+        let tt_move = ChessMove::new(Square::E1, Square::E2, None);
+        MoveOrdering::new().order_moves(&board, &mut moves, Some(tt_move), 0);
+        assert_eq!(moves[0], tt_move);
+        assert_eq!(moves[1], ChessMove::new(Square::E4, Square::D5, None));
+        assert_eq!(moves[2], ChessMove::new(Square::D1, Square::D5, None));
+        assert_eq!(moves[3], ChessMove::new(Square::E4, Square::F5, None));
+    }
+
+    #[test]
+    fn killers_and_history_rank_quiet_moves() {
+        let board = Board::default();
+        let mut ordering = MoveOrdering::new();
+        let killer = ChessMove::new(Square::G1, Square::F3, None);
+        let history = ChessMove::new(Square::B2, Square::B3, None);
+        ordering.record_cutoff(&board, history, 6, 5);
+        ordering.record_cutoff(&board, killer, 1, 3);
+        let mut moves = MoveGen::new_legal(&board).collect::<Vec<ChessMove>>();
+        ordering.order_moves(&board, &mut moves, None, 3);
+        assert_eq!(moves[0], killer);
+        assert_eq!(moves[1], history);
+    }
+}
@@ -0,0 +1,220 @@
+use chess::Board;
+use chess::Color;
+use chess::Piece;
+
+pub const DEFAULT_PAWN_HASH_ENTRIES: usize = 1 << 14;
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+
+// (midgame, endgame) penalties and bonuses in centipawns
+const DOUBLED: (i32, i32) = (-10, -20);
+const ISOLATED: (i32, i32) = (-15, -10);
+const BACKWARD: (i32, i32) = (-8, -10);
+// Indexed by the rank of the pawn relative to its own side
+pub const PASSED_MG: [i32; 8] = [0, 5, 10, 15, 30, 55, 90, 0];
+pub const PASSED_EG: [i32; 8] = [0, 10, 15, 25, 50, 90, 140, 0];
+
+// Zobrist keys for pawns only, the chess crate does not provide a pawn hash
+const PAWN_KEYS: [[u64; 64]; 2] = pawn_keys();
+
+const fn pawn_keys() -> [[u64; 64]; 2] {
+    let mut keys = [[0; 64]; 2];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut color = 0;
+    while color < 2 {
+        let mut sq = 0;
+        while sq < 64 {
+            // splitmix64
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            keys[color][sq] = z ^ (z >> 31);
+            sq += 1;
+        }
+        color += 1;
+    }
+    keys
+}
+
+// Pawn-only terms, scores are from White's point of view
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PawnEntry {
+    key: u64,
+    pub mg: i32,
+    pub eg: i32,
+    pub passed: [u64; 2],
+}
+
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+    mask: usize,
+}
+
+impl PawnTable {
+    pub fn new(entries: usize) -> Self {
+        let count = entries.max(1).next_power_of_two();
+        PawnTable {
+            entries: vec![PawnEntry::default(); count],
+            mask: count - 1,
+        }
+    }
+
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        let key = pawn_key(board);
+        let slot = &mut self.entries[key as usize & self.mask];
+        // A zero key is a board without pawns, which the default entry already describes
+        if slot.key != key {
+            *slot = evaluate_pawns(board);
+            slot.key = key;
+        }
+        *slot
+    }
+}
+
+pub fn pawn_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for color in [Color::White, Color::Black].iter() {
+        for sq in board.pieces(Piece::Pawn) & board.color_combined(*color) {
+            key ^= PAWN_KEYS[color.to_index()][sq.to_index()];
+        }
+    }
+    key
+}
+
+pub fn file_mask(file: usize) -> u64 {
+    FILE_A << file
+}
+
+pub fn adjacent_files(file: usize) -> u64 {
+    let mut mask = 0;
+    if file > 0 {
+        mask |= file_mask(file - 1);
+    }
+    if file < 7 {
+        mask |= file_mask(file + 1);
+    }
+    mask
+}
+
+// Every rank strictly in front of `rank` from the given side's point of view
+pub fn forward_ranks(color: Color, rank: usize) -> u64 {
+    match color {
+        Color::White if rank >= 7 => 0,
+        Color::White => !0u64 << (8 * (rank + 1)),
+        Color::Black => (1u64 << (8 * rank)) - 1,
+    }
+}
+
+pub fn relative_rank(color: Color, rank: usize) -> usize {
+    match color {
+        Color::White => rank,
+        Color::Black => 7 - rank,
+    }
+}
+
+fn evaluate_pawns(board: &Board) -> PawnEntry {
+    let mut entry = PawnEntry::default();
+    let pawns = board.pieces(Piece::Pawn);
+    for color in [Color::White, Color::Black].iter() {
+        let color = *color;
+        let own = (pawns & board.color_combined(color)).0;
+        let theirs = (pawns & board.color_combined(!color)).0;
+        let sign = match color {
+            Color::White => 1,
+            Color::Black => -1,
+        };
+        let mut mg = 0;
+        let mut eg = 0;
+
+        for file in 0..8 {
+            let count = (own & file_mask(file)).count_ones() as i32;
+            if count > 1 {
+                mg += DOUBLED.0 * (count - 1);
+                eg += DOUBLED.1 * (count - 1);
+            }
+        }
+
+        for sq in pawns & board.color_combined(color) {
+            let index = sq.to_index();
+            let (file, rank) = (index % 8, index / 8);
+            let ahead = forward_ranks(color, rank);
+            let neighbours = adjacent_files(file);
+
+            if own & neighbours == 0 {
+                mg += ISOLATED.0;
+                eg += ISOLATED.1;
+            } else if own & neighbours & !ahead == 0 {
+                // Every supporting pawn has already advanced, backward if the
+                // stop square is covered by an enemy pawn
+                let stop_rank = match color {
+                    Color::White => rank + 1,
+                    Color::Black => rank - 1,
+                };
+                let attacker_rank = match color {
+                    Color::White => stop_rank + 1,
+                    Color::Black => stop_rank.wrapping_sub(1),
+                };
+                if attacker_rank < 8 && theirs & neighbours & (0xFF << (8 * attacker_rank)) != 0 {
+                    mg += BACKWARD.0;
+                    eg += BACKWARD.1;
+                }
+            }
+
+            let front_span = ahead & (file_mask(file) | neighbours);
+            if theirs & front_span == 0 && own & ahead & file_mask(file) == 0 {
+                let relative = relative_rank(color, rank);
+                mg += PASSED_MG[relative];
+                eg += PASSED_EG[relative];
+                entry.passed[color.to_index()] |= 1 << index;
+            }
+        }
+
+        entry.mg += sign * mg;
+        entry.eg += sign * eg;
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn pawns(fen: &str) -> PawnEntry {
+        PawnTable::new(16).probe(&Board::from_str(fen).unwrap())
+    }
+
+    #[test]
+    fn detects_passed_pawns() {
+        let entry = pawns("4k3/8/8/3P4/8/8/6p1/4K3 w - - 0 1");
+        assert_eq!(entry.passed[Color::White.to_index()], 1 << 35);
+        assert_eq!(entry.passed[Color::Black.to_index()], 1 << 14);
+        // White's pawn is further from promotion
+        assert!(entry.eg < 0);
+    }
+
+    #[test]
+    fn penalises_doubled_and_isolated_pawns() {
+        let healthy = pawns("4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1");
+        let broken = pawns("4k3/pp6/8/8/8/P7/P7/4K3 w - - 0 1");
+        assert_eq!(healthy.mg, 0);
+        assert!(broken.mg < healthy.mg);
+        assert!(broken.eg < healthy.eg);
+    }
+
+    #[test]
+    fn penalises_backward_pawns() {
+        let backward = pawns("4k3/8/8/3p4/1P6/2P5/8/4K3 w - - 0 1");
+        let supported = pawns("4k3/8/8/3p4/1PP5/8/8/4K3 w - - 0 1");
+        assert!(backward.mg < supported.mg);
+    }
+
+    #[test]
+    fn pawn_key_ignores_other_pieces() {
+        let a = Board::from_str("4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1").unwrap();
+        let b = Board::from_str("3qk3/pp6/8/8/8/8/PP6/R3K3 b - - 0 1").unwrap();
+        assert_eq!(pawn_key(&a), pawn_key(&b));
+        assert_ne!(pawn_key(&a), pawn_key(&Board::default()));
+    }
+}
@@ -0,0 +1,562 @@
+use super::draw::insufficient_material;
+use super::draw::resets_halfmove_clock;
+use super::evaluation::evaluate_board;
+use super::evaluation::piece_value;
+use super::ordering::MoveOrdering;
+use super::pawns::PawnTable;
+use super::pawns::DEFAULT_PAWN_HASH_ENTRIES;
+use super::tablebase::Tablebase;
+use super::transposition::Bound;
+use super::transposition::TranspositionTable;
+use super::transposition::DEFAULT_HASH_MB;
+use super::ChessEngine;
+use chess::Board;
+use chess::BoardStatus;
+use chess::ChessMove;
+use chess::Color;
+use chess::MoveGen;
+use chess::Piece;
+use chess::Square;
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const INFINITY: i32 = 32000;
+pub const MATE: i32 = 31000;
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: Option<ChessMove>,
+    pub score: i32,
+    pub depth: isize,
+    pub pv: Vec<ChessMove>,
+    pub nodes: u64,
+}
+
+impl SearchResult {
+    // Moves until mate, negative when the side to move is getting mated
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score.abs() < MATE - MAX_DEPTH as i32 {
+            return None;
+        }
+        let plies = MATE - self.score.abs();
+        let moves = (plies + 1) / 2;
+        Some(if self.score > 0 { moves } else { -moves })
+    }
+}
+
+impl fmt::Display for SearchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "depth {} score {} nodes {} pv",
+            self.depth, self.score, self.nodes
+        )?;
+        for m in self.pv.iter() {
+            write!(f, " {}", m)?;
+        }
+        Ok(())
+    }
+}
+
+pub const MAX_DEPTH: isize = 64;
+pub const MAX_PLY: usize = 128;
+// Safety margin for delta pruning in the quiescence search
+const DELTA_MARGIN: i32 = 200;
+
+pub struct Searcher {
+    nodes: u64,
+    deadline: Option<Instant>,
+    // Raised from another thread to end the search early
+    stop_signal: Option<Arc<AtomicBool>>,
+    interruptible: bool,
+    stopped: bool,
+    tt: TranspositionTable,
+    ordering: MoveOrdering,
+    pawns: PawnTable,
+    contempt: i32,
+    root_side: Color,
+    // Hashes of every position from the game and the current search line
+    path: Vec<u64>,
+    tablebase: Option<Arc<Tablebase>>,
+    // Kept for reporting once the caller only took the move
+    last_result: Option<SearchResult>,
+}
+
+impl Searcher {
+    pub fn new() -> Self {
+        Searcher::with_hash_size(DEFAULT_HASH_MB)
+    }
+
+    pub fn with_hash_size(size_mb: usize) -> Self {
+        Searcher {
+            nodes: 0,
+            deadline: None,
+            stop_signal: None,
+            interruptible: false,
+            stopped: false,
+            tt: TranspositionTable::new(size_mb),
+            ordering: MoveOrdering::new(),
+            pawns: PawnTable::new(DEFAULT_PAWN_HASH_ENTRIES),
+            contempt: 0,
+            root_side: Color::White,
+            path: Vec::new(),
+            tablebase: None,
+            last_result: None,
+        }
+    }
+
+    pub fn set_stop_signal(&mut self, stop: Arc<AtomicBool>) {
+        self.stop_signal = Some(stop);
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Arc<Tablebase>) {
+        self.tablebase = Some(tablebase);
+    }
+
+    // Positive contempt makes the bot avoid draws against weaker opposition
+    pub fn set_contempt(&mut self, contempt: i32) {
+        self.contempt = contempt;
+    }
+
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+        self.ordering.clear();
+        self.last_result = None;
+    }
+
+    pub fn last_result(&self) -> Option<&SearchResult> {
+        self.last_result.as_ref()
+    }
+
+    // Iterative deepening until max_depth is reached or the time budget runs out,
+    // the result always comes from the last fully completed iteration
+    pub fn iterative_deepening(
+        &mut self,
+        engine: &ChessEngine,
+        max_depth: isize,
+        budget: Option<Duration>,
+    ) -> SearchResult {
+        let start = Instant::now();
+        let board = &engine.bitboard;
+        let halfmove = engine.halfmove_clock;
+        self.nodes = 0;
+        self.stopped = false;
+        self.ordering.new_search();
+        self.root_side = board.side_to_move();
+        self.path.clear();
+        self.path.extend_from_slice(&engine.history);
+
+        if let Some((m, wdl)) = self.tablebase.as_ref().and_then(|tb| tb.probe_root(engine)) {
+            let result = SearchResult {
+                best_move: Some(m),
+                score: wdl.score(0),
+                depth: 0,
+                pv: vec![m],
+                nodes: 0,
+            };
+            self.last_result = Some(result.clone());
+            return result;
+        }
+
+        // Depth 1 always runs to completion so there is a move to play
+        self.deadline = None;
+        self.interruptible = false;
+        let mut best = self.search_depth(board, halfmove, 1);
+        self.deadline = budget.map(|b| start + b);
+        self.interruptible = true;
+
+        for depth in 2..=max_depth.min(MAX_DEPTH) {
+            if let Some(b) = budget {
+                // The next iteration is unlikely to finish in what is left
+                if start.elapsed() > b / 2 {
+                    break;
+                }
+            }
+            if best.score.abs() >= MATE - MAX_DEPTH as i32 || self.stop_requested() {
+                break;
+            }
+            let result = self.search_depth(board, halfmove, depth);
+            if self.stopped {
+                break;
+            }
+            best = result;
+        }
+        best.nodes = self.nodes;
+        self.last_result = Some(best.clone());
+        best
+    }
+
+    fn search_depth(&mut self, board: &Board, halfmove: u32, depth: isize) -> SearchResult {
+        let mut pv = Vec::new();
+        let score = self.negamax(board, halfmove, depth, 0, -INFINITY, INFINITY, &mut pv);
+        SearchResult {
+            best_move: pv.first().copied(),
+            score,
+            depth,
+            pv,
+            nodes: self.nodes,
+        }
+    }
+
+    fn out_of_time(&mut self) -> bool {
+        if self.interruptible && !self.stopped && self.nodes.is_multiple_of(1024) {
+            if let Some(deadline) = self.deadline {
+                self.stopped = Instant::now() >= deadline;
+            }
+            self.stopped |= self.stop_requested();
+        }
+        self.stopped
+    }
+
+    fn stop_requested(&self) -> bool {
+        self.stop_signal
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    // Draws are scored relative to the side the search started for
+    fn draw_score(&self, board: &Board) -> i32 {
+        if board.side_to_move() == self.root_side {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+
+    // Only positions with the same side to move since the last irreversible
+    // move can repeat, a single repetition inside the search counts as a draw
+    fn is_repetition(&self, key: u64, halfmove: u32) -> bool {
+        let len = self.path.len();
+        let window = (halfmove as usize).min(len);
+        self.path[len - window..]
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .any(|h| *h == key)
+    }
+
+    // Negamax with alpha-beta pruning, scores are from the side to move's perspective
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        board: &Board,
+        halfmove: u32,
+        depth: isize,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+        pv: &mut Vec<ChessMove>,
+    ) -> i32 {
+        pv.clear();
+        if depth <= 0 {
+            return self.quiescence(board, ply, alpha, beta);
+        }
+        self.nodes += 1;
+        if self.out_of_time() {
+            return 0;
+        }
+        let key = board.get_hash();
+        if ply > 0
+            && (halfmove >= 100
+                || self.is_repetition(key, halfmove)
+                || insufficient_material(board))
+        {
+            return self.draw_score(board);
+        }
+        match board.status() {
+            BoardStatus::Checkmate => return -MATE + ply,
+            BoardStatus::Stalemate => return self.draw_score(board),
+            BoardStatus::Ongoing => {}
+        }
+        if ply > 0 && halfmove == 0 {
+            if let Some(wdl) = self.tablebase.as_ref().and_then(|tb| tb.probe_wdl(board)) {
+                return match wdl.score(ply) {
+                    0 => self.draw_score(board),
+                    score => score,
+                };
+            }
+        }
+
+        let entry = self.tt.probe(key);
+        let tt_move = entry.and_then(|e| e.best_move);
+        if let Some(e) = entry {
+            if ply > 0 && e.depth as isize >= depth {
+                let score = score_from_tt(e.score, ply);
+                match e.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        let alpha_orig = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        let mut child_pv = Vec::new();
+        let mut moves = MoveGen::new_legal(board).collect::<Vec<ChessMove>>();
+        self.ordering
+            .order_moves(board, &mut moves, tt_move, ply as usize);
+        self.path.push(key);
+        for m in moves {
+            let child = board.make_move_new(m);
+            let child_halfmove = if resets_halfmove_clock(board, m) {
+                0
+            } else {
+                halfmove + 1
+            };
+            let score = -self.negamax(
+                &child,
+                child_halfmove,
+                depth - 1,
+                ply + 1,
+                -beta,
+                -alpha,
+                &mut child_pv,
+            );
+            if self.stopped {
+                self.path.pop();
+                return 0;
+            }
+            if score > best {
+                best = score;
+                best_move = Some(m);
+                if score > alpha {
+                    alpha = score;
+                    pv.clear();
+                    pv.push(m);
+                    pv.extend_from_slice(&child_pv);
+                }
+            }
+            if alpha >= beta {
+                if MoveOrdering::is_quiet(board, m) {
+                    self.ordering.record_cutoff(board, m, depth, ply as usize);
+                }
+                break;
+            }
+        }
+        self.path.pop();
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > alpha_orig {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt
+            .store(key, depth as i16, bound, score_to_tt(best, ply), best_move);
+        best
+    }
+}
+
+impl Searcher {
+    // Resolve captures and promotions at the leaves so the static evaluation
+    // is never taken in the middle of an exchange
+    fn quiescence(&mut self, board: &Board, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        if self.out_of_time() {
+            return 0;
+        }
+        let in_check = board.checkers().popcnt() > 0;
+        if ply as usize >= MAX_PLY {
+            return evaluate_board(board, &mut self.pawns);
+        }
+
+        let mut best = -INFINITY;
+        let mut moves = MoveGen::new_legal(board);
+        if in_check {
+            // No stand pat while in check, every evasion has to be tried
+            if moves.len() == 0 {
+                return -MATE + ply;
+            }
+        } else {
+            let stand_pat = evaluate_board(board, &mut self.pawns);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            if stand_pat > alpha {
+                alpha = stand_pat;
+            }
+            best = stand_pat;
+        }
+
+        let mut captures = moves
+            .by_ref()
+            .filter(|m| in_check || is_tactical(board, *m))
+            .collect::<Vec<ChessMove>>();
+        MoveOrdering::order_captures(board, &mut captures);
+        for m in captures {
+            if !in_check && best + capture_gain(board, m) + DELTA_MARGIN < alpha {
+                continue;
+            }
+            let child = board.make_move_new(m);
+            let score = -self.quiescence(&child, ply + 1, -beta, -alpha);
+            if self.stopped {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                }
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+}
+
+fn is_tactical(board: &Board, m: ChessMove) -> bool {
+    m.get_promotion().is_some()
+        || board.piece_on(m.get_dest()).is_some()
+        || Some(m.get_dest()) == en_passant_target(board)
+}
+
+// The square a pawn lands on when capturing en passant
+fn en_passant_target(board: &Board) -> Option<Square> {
+    board.en_passant().map(|sq| match board.side_to_move() {
+        Color::White => sq.uup(),
+        Color::Black => sq.udown(),
+    })
+}
+
+// Best case material swing of a move, used for delta pruning
+fn capture_gain(board: &Board, m: ChessMove) -> i32 {
+    let captured = match board.piece_on(m.get_dest()) {
+        Some(piece) => piece_value(piece),
+        None if board.piece_on(m.get_source()) == Some(Piece::Pawn)
+            && Some(m.get_dest()) == en_passant_target(board) =>
+        {
+            piece_value(Piece::Pawn)
+        }
+        None => 0,
+    };
+    let promoted = match m.get_promotion() {
+        Some(piece) => piece_value(piece) - piece_value(Piece::Pawn),
+        None => 0,
+    };
+    captured + promoted
+}
+
+// Mate scores are stored relative to the node rather than the root
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE - MAX_DEPTH as i32 {
+        score + ply
+    } else if score <= -MATE + MAX_DEPTH as i32 {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE - MAX_DEPTH as i32 {
+        score - ply
+    } else if score <= -MATE + MAX_DEPTH as i32 {
+        score + ply
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn takes_hanging_queen() {
+        let board = ChessEngine::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1".to_string());
+        let result = Searcher::new().iterative_deepening(&board, 2, None);
+        assert_eq!(result.best_move.unwrap().to_string(), "d2d5");
+        assert_eq!(result.pv[0], result.best_move.unwrap());
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let board = ChessEngine::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1".to_string());
+        let result = Searcher::new().iterative_deepening(&board, 3, None);
+        assert_eq!(result.best_move.unwrap().to_string(), "a1a8");
+        assert_eq!(result.score, MATE - 1);
+        assert_eq!(result.mate_in(), Some(1));
+    }
+
+    #[test]
+    fn quiescence_sees_recapture() {
+        let board = ChessEngine::from_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1".to_string());
+        let result = Searcher::with_hash_size(1).iterative_deepening(&board, 1, None);
+        assert_ne!(result.best_move.unwrap().to_string(), "d1d5");
+        assert!(result.score > 500);
+    }
+
+    #[test]
+    fn transposition_table_keeps_result_stable() {
+        let board = ChessEngine::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1".to_string());
+        let mut searcher = Searcher::with_hash_size(1);
+        let first = searcher.iterative_deepening(&board, 4, None);
+        let second = searcher.iterative_deepening(&board, 4, None);
+        assert_eq!(first.best_move, second.best_move);
+        assert_eq!(first.score, second.score);
+        assert!(second.nodes < first.nodes);
+    }
+
+    #[test]
+    fn fifty_move_rule_scores_as_draw() {
+        let board = ChessEngine::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 99 80".to_string());
+        let result = Searcher::with_hash_size(1).iterative_deepening(&board, 2, None);
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn contempt_applies_to_insufficient_material() {
+        let board = ChessEngine::from_fen("4k3/8/8/8/8/8/3n4/4K3 w - - 0 1".to_string());
+        let mut searcher = Searcher::with_hash_size(1);
+        searcher.set_contempt(30);
+        let result = searcher.iterative_deepening(&board, 2, None);
+        assert_eq!(result.best_move.unwrap().to_string(), "e1d2");
+        assert_eq!(result.score, -30);
+    }
+
+    #[test]
+    fn avoids_repetition_when_winning() {
+        let mut board = ChessEngine::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1".to_string());
+        for m in ["d1d2", "e8f8", "d2d1", "f8e8"].iter() {
+            board = ChessEngine::move_piece(&board, ChessMove::from_str(m).unwrap());
+        }
+        let result = Searcher::with_hash_size(1).iterative_deepening(&board, 3, None);
+        assert_ne!(result.best_move.unwrap().to_string(), "d1d2");
+        assert!(result.score > 500);
+    }
+
+    #[test]
+    fn iterative_deepening_respects_budget() {
+        let board = ChessEngine::default();
+        let start = Instant::now();
+        let result = Searcher::new().iterative_deepening(
+            &board,
+            MAX_DEPTH,
+            Some(Duration::from_millis(200)),
+        );
+        assert!(start.elapsed() < Duration::from_millis(1000));
+        assert!(result.best_move.is_some());
+        assert!(result.depth >= 1);
+    }
+
+    #[test]
+    fn stop_signal_ends_search_after_first_iteration() {
+        let mut searcher = Searcher::new();
+        searcher.set_stop_signal(Arc::new(AtomicBool::new(true)));
+        let result = searcher.iterative_deepening(&ChessEngine::default(), MAX_DEPTH, None);
+        assert_eq!(result.depth, 1);
+        assert!(result.best_move.is_some());
+    }
+}
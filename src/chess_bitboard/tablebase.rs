@@ -0,0 +1,274 @@
+use super::ChessEngine;
+use chess::get_bishop_moves;
+use chess::get_king_moves;
+use chess::get_knight_moves;
+use chess::get_pawn_attacks;
+use chess::get_rook_moves;
+use chess::BitBoard;
+use chess::Board;
+use chess::CastleRights;
+use chess::ChessMove;
+use chess::Color;
+use chess::Piece;
+use chess::Square;
+use chess::ALL_SQUARES;
+use pyrrhic_rs::DtzProbeValue;
+use pyrrhic_rs::EngineAdapter;
+use pyrrhic_rs::TableBases;
+use pyrrhic_rs::WdlProbeResult;
+use std::fs;
+use std::sync::Mutex;
+
+// Tablebase wins rank below every real mate the search can find
+pub const TB_WIN: i32 = 20000;
+
+// Lets the Syzygy prober use the chess crate's attack tables
+#[derive(Clone)]
+struct ChessAdapter;
+
+impl EngineAdapter for ChessAdapter {
+    fn pawn_attacks(color: pyrrhic_rs::Color, square: u64) -> u64 {
+        let color = match color {
+            pyrrhic_rs::Color::White => Color::White,
+            pyrrhic_rs::Color::Black => Color::Black,
+        };
+        get_pawn_attacks(to_square(square), color, !BitBoard(0)).0
+    }
+    fn knight_attacks(square: u64) -> u64 {
+        get_knight_moves(to_square(square)).0
+    }
+    fn bishop_attacks(square: u64, occupied: u64) -> u64 {
+        get_bishop_moves(to_square(square), BitBoard(occupied)).0
+    }
+    fn rook_attacks(square: u64, occupied: u64) -> u64 {
+        get_rook_moves(to_square(square), BitBoard(occupied)).0
+    }
+    fn queen_attacks(square: u64, occupied: u64) -> u64 {
+        (get_bishop_moves(to_square(square), BitBoard(occupied))
+            | get_rook_moves(to_square(square), BitBoard(occupied)))
+        .0
+    }
+    fn king_attacks(square: u64) -> u64 {
+        get_king_moves(to_square(square)).0
+    }
+}
+
+fn to_square(index: u64) -> Square {
+    ALL_SQUARES[index as usize]
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    fn from_probe(result: WdlProbeResult) -> Self {
+        match result {
+            WdlProbeResult::Loss => Wdl::Loss,
+            WdlProbeResult::BlessedLoss => Wdl::BlessedLoss,
+            WdlProbeResult::Draw => Wdl::Draw,
+            WdlProbeResult::CursedWin => Wdl::CursedWin,
+            WdlProbeResult::Win => Wdl::Win,
+        }
+    }
+
+    // Search score for the side to move, cursed wins and blessed losses are
+    // draws under the fifty-move rule
+    pub fn score(self, ply: i32) -> i32 {
+        match self {
+            Wdl::Win => TB_WIN - ply,
+            Wdl::Loss => -TB_WIN + ply,
+            Wdl::CursedWin | Wdl::BlessedLoss | Wdl::Draw => 0,
+        }
+    }
+}
+
+// Piece bitboards in the argument order the prober expects
+struct ProbePosition {
+    white: u64,
+    black: u64,
+    kings: u64,
+    queens: u64,
+    rooks: u64,
+    bishops: u64,
+    knights: u64,
+    pawns: u64,
+    ep: u32,
+    turn: bool,
+}
+
+impl ProbePosition {
+    fn from_board(board: &Board) -> Self {
+        let ep = board
+            .en_passant()
+            .map(|sq| match board.side_to_move() {
+                Color::White => sq.uup(),
+                Color::Black => sq.udown(),
+            })
+            .map(|sq| sq.to_index() as u32)
+            .unwrap_or(0);
+        ProbePosition {
+            white: board.color_combined(Color::White).0,
+            black: board.color_combined(Color::Black).0,
+            kings: board.pieces(Piece::King).0,
+            queens: board.pieces(Piece::Queen).0,
+            rooks: board.pieces(Piece::Rook).0,
+            bishops: board.pieces(Piece::Bishop).0,
+            knights: board.pieces(Piece::Knight).0,
+            pawns: board.pieces(Piece::Pawn).0,
+            ep,
+            turn: board.side_to_move() == Color::White,
+        }
+    }
+}
+
+// Local Syzygy WDL/DTZ files, the prober keeps global state so there is only
+// ever one of these and it is shared between games
+pub struct Tablebase {
+    tables: Mutex<TableBases<ChessAdapter>>,
+    max_pieces: u32,
+}
+
+impl Tablebase {
+    pub fn open(path: &str) -> Result<Self, String> {
+        // The prober treats missing files as present, so check for WDL tables first
+        let has_tables = path.split(':').any(|dir| {
+            fs::read_dir(dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(|e| e.ok())
+                        .any(|e| e.path().extension().is_some_and(|ext| ext == "rtbw"))
+                })
+                .unwrap_or(false)
+        });
+        if !has_tables {
+            return Err(format!("{}: no Syzygy tables found", path));
+        }
+        let tables =
+            TableBases::<ChessAdapter>::new(path).map_err(|e| format!("{}: {:?}", path, e))?;
+        let max_pieces = tables.max_pieces();
+        Ok(Tablebase {
+            tables: Mutex::new(tables),
+            max_pieces,
+        })
+    }
+
+    pub fn max_pieces(&self) -> u32 {
+        self.max_pieces
+    }
+
+    // Tables carry no castling information
+    pub fn can_probe(&self, board: &Board) -> bool {
+        board.combined().popcnt() <= self.max_pieces
+            && board.castle_rights(Color::White) == CastleRights::NoRights
+            && board.castle_rights(Color::Black) == CastleRights::NoRights
+    }
+
+    // Only reliable straight after a capture or pawn move
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        let p = ProbePosition::from_board(board);
+        let tables = self.tables.lock().ok()?;
+        tables
+            .probe_wdl(
+                p.white, p.black, p.kings, p.queens, p.rooks, p.bishops, p.knights, p.pawns, p.ep,
+                p.turn,
+            )
+            .ok()
+            .map(Wdl::from_probe)
+    }
+
+    // Nobody can force a win, the fifty-move rule included
+    pub fn is_draw(&self, board: &Board) -> bool {
+        matches!(
+            self.probe_wdl(board),
+            Some(Wdl::BlessedLoss | Wdl::Draw | Wdl::CursedWin)
+        )
+    }
+
+    // DTZ-optimal move for the root position, taking the fifty-move counter into account
+    pub fn probe_root(&self, engine: &ChessEngine) -> Option<(ChessMove, Wdl)> {
+        let board = &engine.bitboard;
+        if !self.can_probe(board) {
+            return None;
+        }
+        let p = ProbePosition::from_board(board);
+        let tables = self.tables.lock().ok()?;
+        let result = tables
+            .probe_root(
+                p.white,
+                p.black,
+                p.kings,
+                p.queens,
+                p.rooks,
+                p.bishops,
+                p.knights,
+                p.pawns,
+                engine.halfmove_clock,
+                p.ep,
+                p.turn,
+            )
+            .ok()?;
+        match result.root {
+            DtzProbeValue::DtzResult(dtz) => {
+                let promotion = match dtz.promotion {
+                    pyrrhic_rs::Piece::Queen => Some(Piece::Queen),
+                    pyrrhic_rs::Piece::Rook => Some(Piece::Rook),
+                    pyrrhic_rs::Piece::Bishop => Some(Piece::Bishop),
+                    pyrrhic_rs::Piece::Knight => Some(Piece::Knight),
+                    _ => None,
+                };
+                let m = ChessMove::new(
+                    to_square(dtz.from_square as u64),
+                    to_square(dtz.to_square as u64),
+                    promotion,
+                );
+                if board.legal(m) {
+                    Some((m, Wdl::from_probe(dtz.wdl)))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_bitboard::MAX_DEPTH;
+    use std::str::FromStr;
+
+    #[test]
+    fn missing_directory_is_an_error() {
+        assert!(Tablebase::open("/nonexistent/syzygy").is_err());
+    }
+
+    #[test]
+    fn probe_position_uses_capture_square_for_en_passant() {
+        let board = Board::from_str("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let p = ProbePosition::from_board(&board);
+        assert_eq!(p.ep, Square::D6.to_index() as u32);
+        assert!(p.turn);
+        assert_eq!(p.kings.count_ones(), 2);
+        assert_eq!(
+            p.pawns,
+            (BitBoard::from_square(Square::D5) | BitBoard::from_square(Square::E5)).0
+        );
+    }
+
+    #[test]
+    fn tablebase_scores_stay_below_mate() {
+        assert!(Wdl::Win.score(MAX_DEPTH as i32) > TB_WIN - MAX_DEPTH as i32 - 1);
+        assert_eq!(Wdl::CursedWin.score(3), 0);
+        assert_eq!(Wdl::Loss.score(2), -(TB_WIN - 2));
+    }
+}
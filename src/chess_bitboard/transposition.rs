@@ -0,0 +1,135 @@
+use chess::ChessMove;
+use std::mem;
+
+pub const DEFAULT_HASH_MB: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Entry {
+    key: u64,
+    pub depth: i16,
+    pub bound: Bound,
+    pub score: i32,
+    pub best_move: Option<ChessMove>,
+}
+
+impl Default for Entry {
+    fn default() -> Self {
+        Entry {
+            key: 0,
+            depth: -1,
+            bound: Bound::Exact,
+            score: 0,
+            best_move: None,
+        }
+    }
+}
+
+// Entries fitting in `bytes`, rounded down to a power of two so the index is
+// a simple mask
+fn entry_count(bytes: usize) -> usize {
+    let count = (bytes / mem::size_of::<Entry>()).max(1);
+    1 << (usize::BITS - 1 - count.leading_zeros())
+}
+
+// Fixed size table indexed by the low bits of the Zobrist key
+pub struct TranspositionTable {
+    entries: Vec<Entry>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let count = entry_count(size_mb.max(1) * 1024 * 1024);
+        TranspositionTable {
+            entries: vec![Entry::default(); count],
+            mask: count - 1,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = Entry::default();
+        }
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let entry = self.entries[key as usize & self.mask];
+        if entry.depth >= 0 && entry.key == key {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    pub fn store(
+        &mut self,
+        key: u64,
+        depth: i16,
+        bound: Bound,
+        score: i32,
+        best_move: Option<ChessMove>,
+    ) {
+        let slot = &mut self.entries[key as usize & self.mask];
+        // Keep deeper results for the same position, always replace other positions
+        if slot.key == key && slot.depth > depth && bound != Bound::Exact {
+            return;
+        }
+        let previous_move = if slot.key == key {
+            slot.best_move
+        } else {
+            None
+        };
+        *slot = Entry {
+            key,
+            depth,
+            bound,
+            score,
+            best_move: best_move.or(previous_move),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::Square;
+
+    #[test]
+    fn stores_and_probes_entries() {
+        let mut tt = TranspositionTable::new(1);
+        let m = ChessMove::new(Square::E2, Square::E4, None);
+        tt.store(42, 3, Bound::Lower, 120, Some(m));
+        let entry = tt.probe(42).unwrap();
+        assert_eq!(entry.depth, 3);
+        assert_eq!(entry.bound, Bound::Lower);
+        assert_eq!(entry.score, 120);
+        assert_eq!(entry.best_move, Some(m));
+        assert!(tt.probe(43).is_none());
+    }
+
+    #[test]
+    fn shallow_bound_does_not_replace_deeper_entry() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(7, 6, Bound::Exact, 50, None);
+        tt.store(7, 2, Bound::Upper, -10, None);
+        assert_eq!(tt.probe(7).unwrap().depth, 6);
+        tt.clear();
+        assert!(tt.probe(7).is_none());
+    }
+
+    #[test]
+    fn size_rounds_down_to_a_power_of_two() {
+        let entry = mem::size_of::<Entry>();
+        assert_eq!(entry_count(entry * 1024), 1024);
+        assert_eq!(entry_count(entry * 1025), 1024);
+        assert_eq!(entry_count(entry * 1023), 512);
+        assert_eq!(entry_count(0), 1);
+    }
+}
@@ -0,0 +1,457 @@
+use crate::chess_bitboard::ChessEngine;
+use crate::chess_bitboard::SearchResult;
+use crate::chess_bitboard::Searcher;
+use crate::chess_bitboard::MAX_DEPTH;
+use chess::ChessMove;
+use std::fmt;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::process::Child;
+use std::process::ChildStdin;
+use std::process::Command;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+// How long an external engine gets to answer `uci` and `isready`
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+// Allowance on top of the move time for the engine to stop and report
+const MOVE_TIME_MARGIN: Duration = Duration::from_secs(2);
+
+// Evaluation for the side the engine moved for
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Score {
+    Centipawns(i32),
+    // Moves until mate, negative when the engine is getting mated
+    Mate(i32),
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Score::Centipawns(cp) => write!(f, "{:+.2}", cp as f64 / 100.0),
+            Score::Mate(moves) if moves > 0 => write!(f, "mate in {}", moves),
+            Score::Mate(moves) => write!(f, "mated in {}", -moves),
+        }
+    }
+}
+
+// What the engine reported about its last move
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchInfo {
+    pub depth: u32,
+    pub score: Score,
+    pub nodes: u64,
+}
+
+impl SearchInfo {
+    fn from_result(result: &SearchResult) -> Self {
+        SearchInfo {
+            depth: result.depth.max(0) as u32,
+            score: match result.mate_in() {
+                Some(moves) => Score::Mate(moves),
+                None => Score::Centipawns(result.score),
+            },
+            nodes: result.nodes,
+        }
+    }
+
+    // The latest scored info line of a search. Lines such as `info depth 9
+    // currmove e2e4 currmovenumber 3` come in between and carry no score.
+    fn last_reported(lines: &[String]) -> Option<Self> {
+        lines
+            .iter()
+            .rev()
+            .filter(|line| line.starts_with("info"))
+            .find_map(|line| SearchInfo::parse_uci(line))
+    }
+
+    // From a UCI line such as `info depth 12 score cp 35 nodes 120000 pv ...`
+    fn parse_uci(line: &str) -> Option<Self> {
+        let tokens = line.split_whitespace().collect::<Vec<&str>>();
+        let value = |name: &str| {
+            let at = tokens.iter().position(|t| *t == name)?;
+            tokens.get(at + 1)
+        };
+        let score = match tokens.iter().position(|t| *t == "score") {
+            Some(at) => match (tokens.get(at + 1), tokens.get(at + 2)) {
+                (Some(&"cp"), Some(cp)) => Score::Centipawns(cp.parse().ok()?),
+                (Some(&"mate"), Some(moves)) => Score::Mate(moves.parse().ok()?),
+                _ => return None,
+            },
+            None => return None,
+        };
+        Some(SearchInfo {
+            depth: value("depth")?.parse().ok()?,
+            score,
+            nodes: value("nodes").and_then(|n| n.parse().ok()).unwrap_or(0),
+        })
+    }
+}
+
+// Anything that can pick a move for the bot in a Lichess game
+pub trait Engine: Send {
+    fn name(&self) -> &str;
+    fn new_game(&mut self) -> Result<(), String>;
+    // `moves` is every move played from `start_fen`, or the standard starting
+    // position when there is none, and `board` is the position after them
+    fn choose_move(
+        &mut self,
+        board: &ChessEngine,
+        start_fen: Option<&str>,
+        moves: &[ChessMove],
+        budget: Duration,
+    ) -> Result<ChessMove, String>;
+    // Details of the search behind the last chosen move, when the engine gives any
+    fn last_search(&self) -> Option<SearchInfo> {
+        None
+    }
+}
+
+pub struct BuiltinEngine {
+    searcher: Searcher,
+}
+
+impl BuiltinEngine {
+    pub fn new(searcher: Searcher) -> Self {
+        BuiltinEngine { searcher }
+    }
+}
+
+impl Engine for BuiltinEngine {
+    fn name(&self) -> &str {
+        "built-in"
+    }
+
+    fn new_game(&mut self) -> Result<(), String> {
+        self.searcher.new_game();
+        Ok(())
+    }
+
+    fn choose_move(
+        &mut self,
+        board: &ChessEngine,
+        _start_fen: Option<&str>,
+        _moves: &[ChessMove],
+        budget: Duration,
+    ) -> Result<ChessMove, String> {
+        Ok(ChessEngine::find_next_move(
+            board,
+            &mut self.searcher,
+            MAX_DEPTH,
+            Some(budget),
+        ))
+    }
+
+    fn last_search(&self) -> Option<SearchInfo> {
+        self.searcher.last_result().map(SearchInfo::from_result)
+    }
+}
+
+// Settings for an engine executable that speaks UCI
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UciEngineConfig {
+    pub path: String,
+    pub hash_mb: Option<usize>,
+    pub threads: Option<usize>,
+    // Extra `setoption` name/value pairs
+    pub options: Vec<(String, String)>,
+}
+
+impl UciEngineConfig {
+    // Options are written as `Name=value` pairs separated by semicolons
+    pub fn parse_options(options: &str) -> Vec<(String, String)> {
+        options
+            .split(';')
+            .filter_map(|option| {
+                let mut parts = option.splitn(2, '=');
+                let name = parts.next()?.trim();
+                let value = parts.next()?.trim();
+                if name.is_empty() {
+                    None
+                } else {
+                    Some((name.to_string(), value.to_string()))
+                }
+            })
+            .collect()
+    }
+}
+
+// External engine running as a child process for the length of one game.
+// Its output is read on a separate thread so a silent engine cannot hold
+// up the game past a deadline.
+pub struct UciEngine {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    last_search: Option<SearchInfo>,
+}
+
+impl UciEngine {
+    pub fn spawn(config: &UciEngineConfig) -> Result<Self, String> {
+        let mut child = Command::new(&config.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{}: {}", config.path, e))?;
+        let stdin = child.stdin.take().ok_or("engine stdin unavailable")?;
+        let stdout = child.stdout.take().ok_or("engine stdout unavailable")?;
+        let (sender, lines) = mpsc::channel();
+        // Ends when the engine closes its output or the engine is dropped
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut engine = UciEngine {
+            name: config.path.clone(),
+            child,
+            stdin,
+            lines,
+            last_search: None,
+        };
+
+        engine.send("uci")?;
+        for line in engine.read_until("uciok", REPLY_TIMEOUT)? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+        }
+        if let Some(hash_mb) = config.hash_mb {
+            engine.set_option("Hash", &hash_mb.to_string())?;
+        }
+        if let Some(threads) = config.threads {
+            engine.set_option("Threads", &threads.to_string())?;
+        }
+        for (name, value) in config.options.iter() {
+            engine.set_option(name, value)?;
+        }
+        engine.sync()?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("{}: {}", self.name, e))
+    }
+
+    // Reads lines until one starts with `token`, returning everything read.
+    // An engine that stays quiet past `timeout` is killed.
+    fn read_until(&mut self, token: &str, timeout: Duration) -> Result<Vec<String>, String> {
+        let deadline = Instant::now() + timeout;
+        let mut lines = Vec::new();
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(wait) {
+                Ok(line) => {
+                    let done = line.starts_with(token);
+                    lines.push(line.trim_end().to_string());
+                    if done {
+                        return Ok(lines);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    let _ = self.child.kill();
+                    return Err(format!("{} did not answer in time", self.name));
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(format!("{} exited", self.name)),
+            }
+        }
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    fn sync(&mut self) -> Result<(), String> {
+        self.send("isready")?;
+        self.read_until("readyok", REPLY_TIMEOUT).map(|_| ())
+    }
+}
+
+impl Engine for UciEngine {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn new_game(&mut self) -> Result<(), String> {
+        self.last_search = None;
+        self.send("ucinewgame")?;
+        self.sync()
+    }
+
+    fn choose_move(
+        &mut self,
+        board: &ChessEngine,
+        start_fen: Option<&str>,
+        moves: &[ChessMove],
+        budget: Duration,
+    ) -> Result<ChessMove, String> {
+        self.send(&position_command(start_fen, moves))?;
+        self.send(&format!("go movetime {}", budget.as_millis()))?;
+        let lines = self.read_until("bestmove", budget + MOVE_TIME_MARGIN)?;
+        self.last_search = SearchInfo::last_reported(&lines);
+        let best = lines.last().and_then(|line| parse_bestmove(line));
+        match best {
+            Some(m) if board.bitboard.legal(m) => Ok(m),
+            _ => Err(format!(
+                "{} returned an unusable move: {}",
+                self.name,
+                lines.last().map(String::as_str).unwrap_or("")
+            )),
+        }
+    }
+
+    fn last_search(&self) -> Option<SearchInfo> {
+        self.last_search.clone()
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn position_command(start_fen: Option<&str>, moves: &[ChessMove]) -> String {
+    let mut command = match start_fen {
+        Some(fen) => format!("position fen {}", fen),
+        None => "position startpos".to_string(),
+    };
+    if !moves.is_empty() {
+        command.push_str(" moves");
+        for m in moves {
+            command.push_str(&format!(" {}", m));
+        }
+    }
+    command
+}
+
+fn parse_bestmove(line: &str) -> Option<ChessMove> {
+    let mut tokens = line.split_whitespace();
+    match tokens.next() {
+        Some("bestmove") => tokens.next().and_then(|m| ChessMove::from_str(m).ok()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bestmove_lines() {
+        assert_eq!(
+            parse_bestmove("bestmove e7e8q ponder d1d8").map(|m| m.to_string()),
+            Some("e7e8q".to_string())
+        );
+        assert_eq!(parse_bestmove("bestmove (none)"), None);
+        assert_eq!(parse_bestmove("info depth 3"), None);
+    }
+
+    #[test]
+    fn builds_position_from_move_list() {
+        let moves = vec![
+            ChessMove::from_str("e2e4").unwrap(),
+            ChessMove::from_str("c7c5").unwrap(),
+        ];
+        assert_eq!(position_command(None, &[]), "position startpos");
+        assert_eq!(
+            position_command(None, &moves),
+            "position startpos moves e2e4 c7c5"
+        );
+        assert_eq!(
+            position_command(Some("8/8/8/8/8/8/8/K1k5 w - - 0 1"), &moves[..1]),
+            "position fen 8/8/8/8/8/8/8/K1k5 w - - 0 1 moves e2e4"
+        );
+    }
+
+    #[test]
+    fn parses_option_pairs() {
+        assert_eq!(
+            UciEngineConfig::parse_options("SyzygyPath=/tb; Move Overhead = 100;;bad"),
+            vec![
+                ("SyzygyPath".to_string(), "/tb".to_string()),
+                ("Move Overhead".to_string(), "100".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_executable_is_an_error() {
+        let config = UciEngineConfig {
+            path: "/nonexistent/engine".to_string(),
+            ..UciEngineConfig::default()
+        };
+        assert!(UciEngine::spawn(&config).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn silent_engine_times_out() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Handles the handshake but never answers `go`
+        let path = std::env::temp_dir().join(format!("silent_engine_{}.sh", std::process::id()));
+        std::fs::write(
+            &path,
+            "#!/bin/sh\nwhile read cmd; do\n  case \"$cmd\" in\n    uci) echo uciok ;;\n    isready) echo readyok ;;\n  esac\ndone\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = UciEngineConfig {
+            path: path.to_string_lossy().to_string(),
+            ..UciEngineConfig::default()
+        };
+        let mut engine = UciEngine::spawn(&config).unwrap();
+        let started = Instant::now();
+        let result = engine.choose_move(
+            &ChessEngine::default(),
+            None,
+            &[],
+            Duration::from_millis(100),
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.unwrap_err().contains("did not answer"));
+        assert!(started.elapsed() < Duration::from_millis(100) + MOVE_TIME_MARGIN * 2);
+    }
+
+    #[test]
+    fn reads_search_info_from_uci_lines() {
+        assert_eq!(
+            SearchInfo::parse_uci("info depth 12 seldepth 18 score cp -35 nodes 120000 pv e2e4"),
+            Some(SearchInfo {
+                depth: 12,
+                score: Score::Centipawns(-35),
+                nodes: 120000,
+            })
+        );
+        let mate = SearchInfo::parse_uci("info depth 5 score mate -2 pv e2e4").unwrap();
+        assert_eq!(mate.score.to_string(), "mated in 2");
+        assert_eq!(Score::Centipawns(35).to_string(), "+0.35");
+        assert_eq!(SearchInfo::parse_uci("info string hello"), None);
+
+        let lines = [
+            "info depth 9 score cp 20 nodes 900 pv e2e4",
+            "info depth 10 currmove d2d4 currmovenumber 2",
+            "bestmove e2e4",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect::<Vec<String>>();
+        assert_eq!(SearchInfo::last_reported(&lines).unwrap().depth, 9);
+        assert_eq!(SearchInfo::last_reported(&lines[1..]), None);
+    }
+}
@@ -0,0 +1,14 @@
+pub use self::backoff::Backoff;
+pub use self::client::ApiError;
+pub use self::client::LichessClient;
+pub use self::client::DEFAULT_BASE_URL;
+pub use self::model::Challenge;
+pub use self::model::ChatLine;
+pub use self::model::Event;
+pub use self::model::GameEvent;
+pub use self::model::GameState;
+mod backoff;
+mod client;
+mod model;
+mod ndjson;
+mod pacer;
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+// Delays between reconnection attempts: doubling from one second up to a
+// minute, each randomly shortened by up to half so that many clients (or
+// many games) dropped at once do not all come back at the same moment
+#[derive(Debug, Default)]
+pub struct Backoff {
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    // Call once a connection works again
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let ceiling = FIRST_DELAY
+            .checked_mul(1 << self.failures.min(16))
+            .unwrap_or(MAX_DELAY)
+            .min(MAX_DELAY);
+        self.failures += 1;
+        ceiling.mul_f64(0.5 + rand::random::<f64>() * 0.5)
+    }
+
+    pub async fn wait(&mut self) {
+        tokio::time::sleep(self.next_delay()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_a_minute_with_jitter() {
+        let mut backoff = Backoff::default();
+        let delays = (0..10)
+            .map(|_| backoff.next_delay())
+            .collect::<Vec<Duration>>();
+        for (i, delay) in delays.iter().enumerate() {
+            let ceiling = Duration::from_secs(1 << i).min(MAX_DELAY);
+            assert!(*delay <= ceiling && *delay >= ceiling / 2, "{:?}", delays);
+        }
+        assert_eq!(backoff.failures(), 10);
+        backoff.reset();
+        assert!(backoff.next_delay() <= FIRST_DELAY);
+    }
+}
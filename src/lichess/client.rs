@@ -0,0 +1,238 @@
+use super::model::Account;
+use super::ndjson::NdjsonStream;
+use super::pacer::Pacer;
+use chess::ChessMove;
+use reqwest::header;
+use reqwest::RequestBuilder;
+use reqwest::Response;
+use reqwest::StatusCode;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const DEFAULT_BASE_URL: &str = "https://lichess.org";
+// Lichess asks bots to identify themselves rather than pose as a browser or curl
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+// Smallest gap between two requests from any game or the event loop
+const REQUEST_INTERVAL: Duration = Duration::from_millis(100);
+// Lichess wants a full minute of silence after answering 429
+const RATE_LIMIT_PAUSE: Duration = Duration::from_secs(60);
+// Tries for requests that are safe to repeat, with a growing delay in between
+const GET_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// A failed API call, `status` is None when no response came back at all
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: Option<StatusCode>,
+    message: String,
+}
+
+impl ApiError {
+    // Network trouble, rate limiting and server errors may pass, a refusal will not
+    pub fn is_transient(&self) -> bool {
+        match self.status {
+            None => true,
+            Some(status) => status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+        }
+    }
+
+    // The token is wrong, revoked or lacks the bot scope, no retry will help
+    pub fn is_unauthorized(&self) -> bool {
+        self.status == Some(StatusCode::UNAUTHORIZED) || self.status == Some(StatusCode::FORBIDDEN)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// Authenticated access to the Lichess bot API. Clones share the connection
+// pool and the request pacing, so every game can hold its own.
+#[derive(Clone)]
+pub struct LichessClient {
+    http: reqwest::Client,
+    // Scheme and host without a trailing slash, e.g. https://lichess.org
+    base_url: String,
+    token: String,
+    pacer: Arc<Mutex<Pacer>>,
+}
+
+impl LichessClient {
+    pub fn new(base_url: &str, token: &str) -> Result<Self, String> {
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(LichessClient {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            pacer: Arc::new(Mutex::new(Pacer::new(REQUEST_INTERVAL))),
+        })
+    }
+
+    pub async fn account(&self) -> Result<Account, ApiError> {
+        let path = "/api/account";
+        let response = self.get(path, "application/json").await?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| ApiError {
+            status: None,
+            message: format!("GET {}: {}", path, e),
+        })?;
+        serde_json::from_str(&body).map_err(|e| ApiError {
+            status: Some(status),
+            message: format!("GET {}: {}", path, e),
+        })
+    }
+
+    pub async fn stream_events(&self) -> Result<NdjsonStream, ApiError> {
+        self.stream("/api/stream/event").await
+    }
+
+    pub async fn stream_game(&self, game_id: &str) -> Result<NdjsonStream, ApiError> {
+        self.stream(&format!("/api/bot/game/stream/{}", game_id))
+            .await
+    }
+
+    // Not retried here, the game loop decides whether the move is still worth sending
+    pub async fn post_move(&self, game_id: &str, m: ChessMove) -> Result<(), ApiError> {
+        self.post(&format!("/api/bot/game/{}/move/{}", game_id, m), &[])
+            .await
+    }
+
+    pub async fn resign(&self, game_id: &str) -> Result<(), ApiError> {
+        self.post(&format!("/api/bot/game/{}/resign", game_id), &[])
+            .await
+    }
+
+    // Only possible before both sides have moved
+    pub async fn abort(&self, game_id: &str) -> Result<(), ApiError> {
+        self.post(&format!("/api/bot/game/{}/abort", game_id), &[])
+            .await
+    }
+
+    // Allowed once an opponent has been gone for claimWinInSeconds
+    pub async fn claim_victory(&self, game_id: &str) -> Result<(), ApiError> {
+        self.post(&format!("/api/bot/game/{}/claim-victory", game_id), &[])
+            .await
+    }
+
+    pub async fn claim_draw(&self, game_id: &str) -> Result<(), ApiError> {
+        self.post(&format!("/api/bot/game/{}/claim-draw", game_id), &[])
+            .await
+    }
+
+    // Offers a draw, or accepts the opponent's offer, when `accept` is set
+    pub async fn answer_draw(&self, game_id: &str, accept: bool) -> Result<(), ApiError> {
+        let answer = if accept { "yes" } else { "no" };
+        self.post(&format!("/api/bot/game/{}/draw/{}", game_id, answer), &[])
+            .await
+    }
+
+    pub async fn answer_takeback(&self, game_id: &str, accept: bool) -> Result<(), ApiError> {
+        let answer = if accept { "yes" } else { "no" };
+        self.post(
+            &format!("/api/bot/game/{}/takeback/{}", game_id, answer),
+            &[],
+        )
+        .await
+    }
+
+    // `room` is "player" or "spectator"
+    pub async fn post_chat(&self, game_id: &str, room: &str, text: &str) -> Result<(), ApiError> {
+        self.post(
+            &format!("/api/bot/game/{}/chat", game_id),
+            &[("room", room), ("text", text)],
+        )
+        .await
+    }
+
+    pub async fn accept_challenge(&self, challenge_id: &str) -> Result<(), ApiError> {
+        self.post(&format!("/api/challenge/{}/accept", challenge_id), &[])
+            .await
+    }
+
+    pub async fn decline_challenge(
+        &self,
+        challenge_id: &str,
+        reason: &str,
+    ) -> Result<(), ApiError> {
+        self.post(
+            &format!("/api/challenge/{}/decline", challenge_id),
+            &[("reason", reason)],
+        )
+        .await
+    }
+
+    async fn stream(&self, path: &str) -> Result<NdjsonStream, ApiError> {
+        let response = self.get(path, "application/x-ndjson").await?;
+        Ok(NdjsonStream::new(response))
+    }
+
+    async fn get(&self, path: &str, accept: &str) -> Result<Response, ApiError> {
+        let mut attempt = 1;
+        loop {
+            let request = self
+                .http
+                .get(format!("{}{}", self.base_url, path))
+                .header(header::ACCEPT, accept);
+            match self.send("GET", path, request).await {
+                Err(e) if e.is_transient() && attempt < GET_ATTEMPTS => {
+                    println!("{}, retrying", e);
+                    tokio::time::sleep(RETRY_DELAY * attempt).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn post(&self, path: &str, form: &[(&str, &str)]) -> Result<(), ApiError> {
+        let mut request = self.http.post(format!("{}{}", self.base_url, path));
+        if !form.is_empty() {
+            request = request.form(form);
+        }
+        self.send("POST", path, request).await.map(|_| ())
+    }
+
+    // Waits for a free slot, sends, and turns an error status into an error
+    // carrying whatever Lichess said about it
+    async fn send(
+        &self,
+        method: &str,
+        path: &str,
+        request: RequestBuilder,
+    ) -> Result<Response, ApiError> {
+        let at = self.pacer.lock().unwrap().reserve(Instant::now());
+        tokio::time::sleep_until(at.into()).await;
+        let response = request
+            .bearer_auth(&self.token)
+            .send()
+            .await
+            .map_err(|e| ApiError {
+                status: None,
+                message: format!("{} {}: {}", method, path, e),
+            })?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            println!("Rate limited by Lichess, pausing requests for a minute");
+            self.pacer
+                .lock()
+                .unwrap()
+                .pause_until(Instant::now() + RATE_LIMIT_PAUSE);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(ApiError {
+            status: Some(status),
+            message: format!("{} {}: {} {}", method, path, status, body.trim()),
+        })
+    }
+}
@@ -0,0 +1,265 @@
+use chess::Color;
+use serde::Deserialize;
+
+// The bot's own account from /api/account
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Account {
+    pub id: String,
+    pub username: String,
+}
+
+// One line of /api/stream/event
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    Challenge {
+        challenge: Challenge,
+    },
+    ChallengeCanceled {
+        challenge: Challenge,
+    },
+    GameStart {
+        game: GameEventInfo,
+    },
+    GameFinish {
+        game: GameEventInfo,
+    },
+    // Event types added by Lichess after this was written
+    #[serde(other)]
+    Unknown,
+}
+
+// One line of /api/bot/game/stream/{id}
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum GameEvent {
+    GameFull(Box<GameFull>),
+    GameState(GameState),
+    ChatLine(ChatLine),
+    OpponentGone(OpponentGone),
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Challenge {
+    pub id: String,
+    pub challenger: Option<ChallengeUser>,
+    pub variant: Option<Variant>,
+    #[serde(default)]
+    pub rated: bool,
+    pub speed: Option<String>,
+    pub time_control: Option<TimeControl>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeUser {
+    pub id: String,
+    pub title: Option<String>,
+    pub rating: Option<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Variant {
+    pub key: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeControl {
+    // "clock", "correspondence" or "unlimited"
+    #[serde(rename = "type")]
+    pub kind: String,
+    // Seconds
+    pub limit: Option<u64>,
+    pub increment: Option<u64>,
+}
+
+// The `game` object of gameStart and gameFinish events
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GameEventInfo {
+    pub id: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GameFull {
+    #[serde(default)]
+    pub rated: bool,
+    pub white: Player,
+    pub black: Player,
+    // "startpos" or a FEN
+    pub initial_fen: Option<String>,
+    pub state: GameState,
+}
+
+impl GameFull {
+    // Which side `user_id` plays, None for spectators. Lichess ids are
+    // lowercase usernames but compare loosely in case a name slipped in.
+    pub fn color_of(&self, user_id: &str) -> Option<Color> {
+        let plays = |player: &Player| {
+            player
+                .id
+                .as_deref()
+                .is_some_and(|id| id.eq_ignore_ascii_case(user_id))
+        };
+        if plays(&self.white) {
+            Some(Color::White)
+        } else if plays(&self.black) {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+}
+
+// Either side of a game, AI opponents only have an aiLevel
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Player {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub ai_level: Option<u8>,
+}
+
+impl Player {
+    pub fn is_human(&self) -> bool {
+        self.ai_level.is_none() && self.title.as_deref() != Some("BOT")
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GameState {
+    // Every move so far in UCI notation, separated by spaces
+    #[serde(default)]
+    pub moves: String,
+    // Milliseconds, absent for correspondence games
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub wdraw: bool,
+    #[serde(default)]
+    pub bdraw: bool,
+    #[serde(default)]
+    pub wtakeback: bool,
+    #[serde(default)]
+    pub btakeback: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ChatLine {
+    pub username: String,
+    pub text: String,
+    // "player" or "spectator"
+    pub room: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OpponentGone {
+    pub gone: bool,
+    pub claim_win_in_seconds: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_event_stream_lines() {
+        let line = r##"{"type":"challenge","challenge":{"id":"7pGLxJ4F","url":"https://lichess.org/7pGLxJ4F","status":"created","challenger":{"id":"lovlas","name":"Lovlas","title":null,"rating":1506,"provisional":true,"online":true},"destUser":{"id":"gambinobot","name":"gambinobot","title":"BOT","rating":1500},"variant":{"key":"standard","name":"Standard","short":"Std"},"rated":true,"speed":"rapid","timeControl":{"type":"clock","limit":600,"increment":0,"show":"10+0"},"color":"random","perf":{"icon":"#","name":"Rapid"}}}"##;
+        match serde_json::from_str::<Event>(line).unwrap() {
+            Event::Challenge { challenge } => {
+                assert_eq!(challenge.id, "7pGLxJ4F");
+                assert!(challenge.rated);
+                assert_eq!(challenge.challenger.unwrap().rating, Some(1506));
+                assert_eq!(challenge.variant.unwrap().key, "standard");
+                let clock = challenge.time_control.unwrap();
+                assert_eq!((clock.kind.as_str(), clock.limit), ("clock", Some(600)));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let line = r#"{"type":"gameStart","game":{"gameId":"abcd1234","fullId":"abcd1234wxyz","id":"abcd1234","color":"white","isMyTurn":true,"opponent":{"id":"lovlas","username":"Lovlas","rating":1500}}}"#;
+        match serde_json::from_str::<Event>(line).unwrap() {
+            Event::GameStart { game } => {
+                assert_eq!(game.id, "abcd1234");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tolerates_unknown_event_types() {
+        let line = r#"{"type":"somethingNew","payload":[1,2,3]}"#;
+        assert_eq!(serde_json::from_str::<Event>(line).unwrap(), Event::Unknown);
+        assert_eq!(
+            serde_json::from_str::<GameEvent>(line).unwrap(),
+            GameEvent::Unknown
+        );
+    }
+
+    #[test]
+    fn parses_game_stream_lines() {
+        let line = r#"{"type":"gameFull","id":"abcd1234","rated":false,"variant":{"key":"standard","name":"Standard","short":"Std"},"clock":{"initial":300000,"increment":3000},"speed":"blitz","perf":{"name":"Blitz"},"createdAt":1600000000000,"white":{"id":"gambinobot","name":"gambinobot","title":"BOT","rating":1500},"black":{"aiLevel":3},"initialFen":"startpos","state":{"type":"gameState","moves":"e2e4 c7c5","wtime":298000,"btime":300000,"winc":3000,"binc":3000,"status":"started"}}"#;
+        match serde_json::from_str::<GameEvent>(line).unwrap() {
+            GameEvent::GameFull(game) => {
+                assert_eq!(game.white.id.as_deref(), Some("gambinobot"));
+                assert_eq!(game.black.ai_level, Some(3));
+                assert_eq!(game.state.moves, "e2e4 c7c5");
+                assert_eq!(game.state.wtime, Some(298000));
+                assert!(!game.state.wdraw);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let line = r#"{"type":"gameState","moves":"","wtime":1,"btime":2,"winc":0,"binc":0,"status":"started","wdraw":true}"#;
+        match serde_json::from_str::<GameEvent>(line).unwrap() {
+            GameEvent::GameState(state) => {
+                assert_eq!(state.moves, "");
+                assert!(state.wdraw && !state.btakeback);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let line = r#"{"type":"chatLine","username":"Lovlas","text":"hi","room":"player"}"#;
+        assert!(matches!(
+            serde_json::from_str::<GameEvent>(line).unwrap(),
+            GameEvent::ChatLine(ChatLine { ref text, .. }) if text == "hi"
+        ));
+
+        let line = r#"{"type":"opponentGone","gone":true,"claimWinInSeconds":8}"#;
+        assert_eq!(
+            serde_json::from_str::<GameEvent>(line).unwrap(),
+            GameEvent::OpponentGone(OpponentGone {
+                gone: true,
+                claim_win_in_seconds: Some(8)
+            })
+        );
+    }
+
+    #[test]
+    fn finds_the_bots_colour_by_id() {
+        let line = r#"{"type":"gameFull","id":"abcd1234","white":{"aiLevel":2},"black":{"id":"mybot","name":"MyBot"},"state":{"moves":""}}"#;
+        let game = match serde_json::from_str::<GameEvent>(line).unwrap() {
+            GameEvent::GameFull(game) => game,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(game.color_of("MyBot"), Some(Color::Black));
+        assert_eq!(game.color_of("mybot"), Some(Color::Black));
+        assert_eq!(game.color_of("someoneelse"), None);
+
+        let account: Account =
+            serde_json::from_str(r#"{"id":"mybot","username":"MyBot","title":"BOT","perfs":{}}"#)
+                .unwrap();
+        assert_eq!(account.id, "mybot");
+    }
+}
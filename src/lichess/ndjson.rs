@@ -0,0 +1,116 @@
+use serde::de::DeserializeOwned;
+
+// Splits a byte stream into newline-terminated lines, holding back any
+// partial line until the rest of it arrives
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+    }
+
+    pub fn next_line(&mut self) -> Option<String> {
+        let end = self.pending.iter().position(|b| *b == b'\n')?;
+        let line = self.pending.drain(..=end).collect::<Vec<u8>>();
+        Some(String::from_utf8_lossy(&line).trim().to_string())
+    }
+
+    // Whatever is left once the stream has ended
+    pub fn finish(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let line = std::mem::take(&mut self.pending);
+        Some(String::from_utf8_lossy(&line).trim().to_string())
+    }
+}
+
+// Empty lines are keep-alives, anything that does not parse is logged and skipped
+pub fn decode_line<T: DeserializeOwned>(line: &str) -> Option<T> {
+    if line.is_empty() {
+        return None;
+    }
+    match serde_json::from_str(line) {
+        Ok(event) => Some(event),
+        Err(e) => {
+            println!("Ignoring unreadable stream line ({}): {}", e, line);
+            None
+        }
+    }
+}
+
+// A streaming response from Lichess, read one JSON object per line
+pub struct NdjsonStream {
+    response: reqwest::Response,
+    lines: LineBuffer,
+    ended: bool,
+}
+
+impl NdjsonStream {
+    pub fn new(response: reqwest::Response) -> Self {
+        NdjsonStream {
+            response,
+            lines: LineBuffer::default(),
+            ended: false,
+        }
+    }
+
+    // The next event, or Ok(None) once the server closes the stream
+    pub async fn next<T: DeserializeOwned>(&mut self) -> Result<Option<T>, String> {
+        loop {
+            let line = match self.lines.next_line() {
+                Some(line) => line,
+                None if self.ended => match self.lines.finish() {
+                    Some(line) => line,
+                    None => return Ok(None),
+                },
+                None => {
+                    match self.response.chunk().await.map_err(|e| e.to_string())? {
+                        Some(chunk) => self.lines.push(&chunk),
+                        None => self.ended = true,
+                    }
+                    continue;
+                }
+            };
+            if let Some(event) = decode_line(&line) {
+                return Ok(Some(event));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn reassembles_lines_split_across_chunks() {
+        let mut lines = LineBuffer::default();
+        lines.push(b"{\"type\":\"gameSt");
+        assert_eq!(lines.next_line(), None);
+        lines.push(b"ate\"}\n\n{\"a\":");
+        assert_eq!(
+            lines.next_line().as_deref(),
+            Some("{\"type\":\"gameState\"}")
+        );
+        assert_eq!(lines.next_line().as_deref(), Some(""));
+        assert_eq!(lines.next_line(), None);
+        lines.push(b"1}");
+        assert_eq!(lines.finish().as_deref(), Some("{\"a\":1}"));
+        assert_eq!(lines.finish(), None);
+    }
+
+    #[test]
+    fn skips_keep_alives_and_garbage() {
+        assert_eq!(decode_line::<Value>(""), None);
+        assert_eq!(decode_line::<Value>("not json"), None);
+        assert_eq!(
+            decode_line::<Value>("{\"type\":\"ping\"}").unwrap()["type"],
+            "ping"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+use std::time::Instant;
+
+// Spaces out requests shared by every clone of the client, and holds all of
+// them back after Lichess answers 429
+#[derive(Debug)]
+pub struct Pacer {
+    interval: Duration,
+    next: Instant,
+}
+
+impl Pacer {
+    pub fn new(interval: Duration) -> Self {
+        Pacer {
+            interval,
+            next: Instant::now(),
+        }
+    }
+
+    // Books the earliest free slot and returns when the request may be sent
+    pub fn reserve(&mut self, now: Instant) -> Instant {
+        let at = self.next.max(now);
+        self.next = at + self.interval;
+        at
+    }
+
+    pub fn pause_until(&mut self, until: Instant) {
+        self.next = self.next.max(until);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spaces_requests_and_pauses_after_rate_limit() {
+        let interval = Duration::from_millis(100);
+        let mut pacer = Pacer::new(interval);
+        let now = Instant::now();
+        assert_eq!(pacer.reserve(now), now);
+        assert_eq!(pacer.reserve(now), now + interval);
+        // A quiet spell does not build up a burst of free slots
+        let later = now + Duration::from_secs(5);
+        assert_eq!(pacer.reserve(later), later);
+
+        pacer.pause_until(later + Duration::from_secs(60));
+        assert_eq!(
+            pacer.reserve(later + Duration::from_secs(1)),
+            later + Duration::from_secs(60)
+        );
+        // An earlier pause never shortens a longer one
+        pacer.pause_until(now);
+        assert_eq!(
+            pacer.reserve(later),
+            later + Duration::from_secs(60) + interval
+        );
+    }
+}
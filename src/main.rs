@@ -1,168 +1,787 @@
+mod absence;
+mod adjudication;
+mod challenges;
+mod chat;
 mod chess_bitboard;
+mod engine;
+mod lichess;
+mod takeback;
+mod uci;
+mod xboard;
+use crate::absence::AbsenceAction;
+use crate::absence::AbsencePolicy;
+use crate::absence::AbsenceWatch;
+use crate::absence::Claim;
+use crate::adjudication::AdjudicationConfig;
+use crate::adjudication::Adjudicator;
+use crate::challenges::Admission;
+use crate::challenges::ChallengePolicy;
+use crate::challenges::GameSlots;
+use crate::chat::Chat;
+use crate::chat::ChatConfig;
+use crate::chess_bitboard::allocate_time;
+use crate::chess_bitboard::BookSelection;
 use crate::chess_bitboard::ChessEngine;
-use crate::chess_bitboard::FromString;
-use bufstream::BufStream;
+use crate::chess_bitboard::OpeningBook;
+use crate::chess_bitboard::Searcher;
+use crate::chess_bitboard::Tablebase;
+use crate::engine::BuiltinEngine;
+use crate::engine::Engine;
+use crate::engine::UciEngine;
+use crate::engine::UciEngineConfig;
+use crate::lichess::ApiError;
+use crate::lichess::Backoff;
+use crate::lichess::Event;
+use crate::lichess::GameEvent;
+use crate::lichess::GameState;
+use crate::lichess::LichessClient;
+use crate::lichess::DEFAULT_BASE_URL;
+use crate::takeback::TakebackPolicy;
+use chess::Board;
 use chess::ChessMove;
 use chess::Color;
-use native_tls::TlsConnector;
-use native_tls::TlsStream;
-use reqwest::header;
-use serde_json::Value;
-use std::io::BufRead;
-use std::io::Write;
-use std::net::TcpStream;
-use std::thread;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
-fn try_parse_json(json_string: &str) -> serde_json::Result<Value> {
-    let v: Value = serde_json::from_str(json_string)?;
-    return Ok(v);
+// Correspondence games have no clock in the stream
+const UNTIMED_MOVE_TIME: Duration = Duration::from_secs(5);
+
+fn move_time_budget(state: &GameState, bot_team: Color) -> Duration {
+    let (time, inc) = match bot_team {
+        Color::White => (state.wtime, state.winc),
+        Color::Black => (state.btime, state.binc),
+    };
+    match time {
+        Some(remaining) => allocate_time(
+            Duration::from_millis(remaining),
+            Duration::from_millis(inc.unwrap_or(0)),
+        ),
+        None => UNTIMED_MOVE_TIME,
+    }
+}
+
+// Plies into the game after which the opening book is no longer consulted
+const DEFAULT_BOOK_DEPTH: usize = 20;
+
+fn load_book() -> Option<OpeningBook> {
+    let path = std::env::var("lichess_book").ok()?;
+    let selection = std::env::var("lichess_book_selection")
+        .ok()
+        .and_then(|name| BookSelection::from_name(&name))
+        .unwrap_or(BookSelection::WeightedRandom);
+    let max_depth = std::env::var("lichess_book_depth")
+        .ok()
+        .and_then(|depth| depth.parse().ok())
+        .unwrap_or(DEFAULT_BOOK_DEPTH);
+    match OpeningBook::open(&path, selection, max_depth) {
+        Ok(book) => {
+            println!("Loaded {} book entries from {}", book.len(), path);
+            Some(book)
+        }
+        Err(e) => {
+            println!("Opening book disabled: {}", e);
+            None
+        }
+    }
 }
 
-fn connect_tls_stream() -> Result<TlsStream<TcpStream>, String> {
-    println!("TLS connect starting");
-    let connector = TlsConnector::new().unwrap();
-    let tcp_stream = match TcpStream::connect("lichess.org:443".to_owned()) {
-        Ok(stream) => stream,
-        Err(e) => return Err(e.to_string()),
+fn load_tablebase() -> Option<Arc<Tablebase>> {
+    let path = std::env::var("lichess_syzygy_path").ok()?;
+    match Tablebase::open(&path) {
+        Ok(tablebase) => {
+            eprintln!(
+                "Loaded Syzygy tablebases up to {} pieces from {}",
+                tablebase.max_pieces(),
+                path
+            );
+            Some(Arc::new(tablebase))
+        }
+        Err(e) => {
+            eprintln!("Tablebases disabled: {}", e);
+            None
+        }
+    }
+}
+
+fn builtin_engine(tablebase: &Option<Arc<Tablebase>>) -> BuiltinEngine {
+    let mut searcher = match std::env::var("lichess_hash_mb").map(|mb| mb.parse()) {
+        Ok(Ok(hash_mb)) => Searcher::with_hash_size(hash_mb),
+        _ => Searcher::new(),
     };
-    let tls_stream = match connector.connect(&"lichess.org".to_owned(), tcp_stream) {
-        Ok(stream) => stream,
-        Err(e) => return Err(e.to_string()),
+    if let Ok(Ok(contempt)) = std::env::var("lichess_contempt").map(|cp| cp.parse()) {
+        searcher.set_contempt(contempt);
+    }
+    if let Some(tablebase) = tablebase {
+        searcher.set_tablebase(tablebase.clone());
+    }
+    BuiltinEngine::new(searcher)
+}
+
+fn external_engine_config() -> Option<UciEngineConfig> {
+    let path = std::env::var("lichess_engine_path").ok()?;
+    Some(UciEngineConfig {
+        path,
+        hash_mb: std::env::var("lichess_hash_mb")
+            .ok()
+            .and_then(|mb| mb.parse().ok()),
+        threads: std::env::var("lichess_engine_threads")
+            .ok()
+            .and_then(|threads| threads.parse().ok()),
+        options: std::env::var("lichess_engine_options")
+            .map(|options| UciEngineConfig::parse_options(&options))
+            .unwrap_or_default(),
+    })
+}
+
+// An external UCI engine when one is configured, the built-in search otherwise
+fn create_engine(tablebase: &Option<Arc<Tablebase>>) -> Box<dyn Engine> {
+    if let Some(config) = external_engine_config() {
+        match UciEngine::spawn(&config) {
+            Ok(engine) => {
+                eprintln!("Using external engine {}", engine.name());
+                return Box::new(engine);
+            }
+            Err(e) => eprintln!("External engine unavailable, using built-in: {}", e),
+        }
+    }
+    Box::new(builtin_engine(tablebase))
+}
+
+fn choose_move(
+    board: &ChessEngine,
+    start_fen: &Option<String>,
+    moves: &[ChessMove],
+    engine: &mut Box<dyn Engine>,
+    book: &Option<OpeningBook>,
+    tablebase: &Option<Arc<Tablebase>>,
+    budget: Duration,
+) -> ChessMove {
+    if let Some(book_move) = book.as_ref().and_then(|b| b.find_move(board)) {
+        println!("book move {}", book_move);
+        return book_move;
+    }
+    match engine.choose_move(board, start_fen.as_deref(), moves, budget) {
+        Ok(m) => m,
+        Err(e) => {
+            // The built-in search takes over for the rest of the game
+            println!("{} failed, switching to built-in: {}", engine.name(), e);
+            *engine = Box::new(builtin_engine(tablebase));
+            engine
+                .choose_move(board, start_fen.as_deref(), moves, budget)
+                .expect("built-in engine has no move")
+        }
+    }
+}
+
+// Starting an external engine and its handshake block just like a search
+// does. An engine kept from before a reconnect is only told about the game.
+async fn prepare_engine_in_background(
+    engine: Option<Box<dyn Engine>>,
+    settings: Arc<GameSettings>,
+) -> Result<Box<dyn Engine>, String> {
+    tokio::task::spawn_blocking(move || {
+        let mut engine = engine.unwrap_or_else(|| create_engine(&settings.tablebase));
+        if let Err(e) = engine.new_game() {
+            println!("{} failed, switching to built-in: {}", engine.name(), e);
+            engine = Box::new(builtin_engine(&settings.tablebase));
+        }
+        engine
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// The search holds its thread for up to the whole budget, so it runs on the
+// blocking pool. The engine goes along and comes back with the move.
+async fn choose_move_in_background(
+    mut engine: Box<dyn Engine>,
+    board: ChessEngine,
+    start_fen: Option<String>,
+    moves: Vec<ChessMove>,
+    settings: Arc<GameSettings>,
+    budget: Duration,
+) -> Result<(Box<dyn Engine>, ChessMove), String> {
+    tokio::task::spawn_blocking(move || {
+        let bot_move = choose_move(
+            &board,
+            &start_fen,
+            &moves,
+            &mut engine,
+            &settings.book,
+            &settings.tablebase,
+            budget,
+        );
+        (engine, bot_move)
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// The position after `moves` from `start_fen`, with the moves it took to get
+// there. Every move is checked, since one the board cannot follow would make
+// each later position wrong.
+fn replay(
+    start_fen: &Option<String>,
+    moves: &str,
+) -> Result<(ChessEngine, Vec<ChessMove>), String> {
+    let mut board = match start_fen {
+        Some(fen) => {
+            Board::from_str(fen).map_err(|e| format!("invalid initial position {}: {}", fen, e))?;
+            ChessEngine::from_fen(fen.clone())
+        }
+        None => ChessEngine::default(),
     };
-    return Ok(tls_stream);
+    let mut played = Vec::new();
+    for m in moves.split_whitespace() {
+        let chess_move = ChessMove::from_str(m)
+            .ok()
+            .filter(|chess_move| board.bitboard.legal(*chess_move))
+            .ok_or_else(|| format!("illegal move {}", m))?;
+        board = ChessEngine::move_piece(&board, chess_move);
+        played.push(chess_move);
+    }
+    Ok((board, played))
 }
 
-fn send_msg(tls_stream: &mut TlsStream<TcpStream>, stream_event_msg: &String) {
-    tls_stream.write_all(stream_event_msg.as_bytes()).unwrap();
+// Tries for a move that did not get through, the client paces the retries
+const MOVE_POST_ATTEMPTS: u32 = 3;
+
+// A move lost to a network or server problem is sent again, one Lichess
+// refused (game over, not our turn) is not. The last error comes back to
+// the game loop, which has to resync when the move never arrived.
+async fn post_move(
+    client: &LichessClient,
+    game_id: &str,
+    bot_move: ChessMove,
+) -> Result<(), ApiError> {
+    let mut attempt = 1;
+    loop {
+        match client.post_move(game_id, bot_move).await {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                println!("Failed to send move {} in {}: {}", bot_move, game_id, e);
+                if !e.is_transient() || attempt == MOVE_POST_ATTEMPTS {
+                    return Err(e);
+                }
+            }
+        }
+        attempt += 1;
+    }
 }
 
-async fn play_game(game_id: String) {
-    let mut stream = connect_tls_stream().unwrap();
-    let lichess_api_token = std::env::var("lichess_api_token").unwrap_or("NA".to_string());
-    let stream_event_msg = format!(
-        "GET /api/bot/game/stream/{} HTTP/1.1\nHost: lichess.org\nUser-Agent: curl/7.68.0\nAccept: */*\nAuthorization: Bearer {}\n\n", 
-    game_id, lichess_api_token);
-    send_msg(&mut stream, &stream_event_msg);
-    let mut bot_team = Color::White;
-    let mut stream = BufStream::new(&mut stream);
-    let mut buf = String::new();
-    while stream.read_line(&mut buf).unwrap_or(0) > 0 {
-        match try_parse_json(&buf) {
-            Ok(v) => {
-                // println!("{}", buf);
-                let msg_type = v["type"].to_string();
-                match msg_type.as_ref() {
-                    r#""gameFull""# => {
-                        let mut board = ChessEngine::default();
-                        let white_team = v["white"]["name"].as_str().unwrap();
-                        match white_team {
-                            "gambinobot" => bot_team = Color::White,
-                            _ => bot_team = Color::Black,
-                        }
-                        let game_id = v["id"].as_str().unwrap().to_owned();
-                        println!("{}", v["state"]["moves"].as_str().unwrap());
-                        for next_move in v["state"]["moves"].as_str().unwrap().split_whitespace() {
-                            board = ChessEngine::move_piece(
-                                &board,
-                                ChessMove::from_string(next_move.to_string()),
-                            );
-                        }
-                        println!("{}", board);
-                        if board.next_to_move() == bot_team {
-                            let bot_move = ChessEngine::find_next_move(&board, 1);
-                            let auth_header_value = format!("Bearer {}", lichess_api_token);
-                            let client = reqwest::Client::builder().build().unwrap();
-                            let endpoint = format!(
-                                "https://lichess.org/api/bot/game/{}/move/{}",
-                                game_id, bot_move
-                            );
-                            let _res = client
-                                .post(&endpoint)
-                                .header(header::AUTHORIZATION, auth_header_value)
-                                .send()
-                                .await
-                                .unwrap();
-                        }
+// Everything a game needs besides the connection, shared by all games
+struct GameSettings {
+    book: Option<OpeningBook>,
+    tablebase: Option<Arc<Tablebase>>,
+    chat: ChatConfig,
+    adjudication: AdjudicationConfig,
+    takebacks: TakebackPolicy,
+    absence: AbsencePolicy,
+}
+
+fn is_tablebase_draw(tablebase: &Option<Arc<Tablebase>>, board: &ChessEngine) -> bool {
+    tablebase
+        .as_ref()
+        .is_some_and(|tablebase| tablebase.is_draw(&board.bitboard))
+}
+
+// Chat messages are short and never worth a retry
+async fn say(client: &LichessClient, game_id: &str, room: &str, text: &str) {
+    if let Err(e) = client.post_chat(game_id, room, text).await {
+        println!("Failed to chat in {}: {}", game_id, e);
+    }
+}
+
+async fn say_to_all(client: &LichessClient, game_id: &str, text: &Option<String>) {
+    if let Some(text) = text {
+        for room in ["player", "spectator"].iter() {
+            say(client, game_id, room, text).await;
+        }
+    }
+}
+
+async fn end_abandoned_game(client: &LichessClient, game_id: &str, action: AbsenceAction) {
+    let result = match action {
+        AbsenceAction::Abort => {
+            println!("Aborting {}, the opponent never moved", game_id);
+            client.abort(game_id).await
+        }
+        AbsenceAction::Claim(Claim::Victory) => {
+            println!("Claiming victory in {}, the opponent left", game_id);
+            client.claim_victory(game_id).await
+        }
+        AbsenceAction::Claim(Claim::Draw) => {
+            println!("Claiming a draw in {}, the opponent left", game_id);
+            client.claim_draw(game_id).await
+        }
+    };
+    if let Err(e) = result {
+        println!("Failed to end {}: {}", game_id, e);
+    }
+}
+
+// Consecutive failed reconnects after which a game is abandoned
+const MAX_GAME_RECONNECTS: u32 = 10;
+
+async fn play_game(
+    client: LichessClient,
+    bot_id: Arc<String>,
+    game_id: String,
+    settings: Arc<GameSettings>,
+) {
+    let tablebase = &settings.tablebase;
+    // Started once the game shows the bot is playing, spectating needs none
+    let mut engine: Option<Box<dyn Engine>> = None;
+    let mut adjudicator = Adjudicator::new(settings.adjudication.clone());
+    // The opponent's standing draw offer has been answered
+    let mut draw_answered = false;
+    let mut takeback_answered = false;
+    let mut takebacks_granted = 0;
+    let mut rated = false;
+    let mut human_opponent = false;
+    // Plies in the last state seen, a takeback makes the list shorter
+    let mut plies = 0;
+    let mut absence = AbsenceWatch::new(settings.absence.clone());
+    let mut chat = Chat::new(&bot_id);
+    // The game was seen in progress, so it gets a greeting and a goodbye
+    let mut greeted = false;
+    let mut bot_team = None;
+    let mut start_fen = None;
+    let mut backoff = Backoff::default();
+    // Lichess closes the stream once the game is over, any earlier end is a
+    // dropped connection and the game is picked up again by id
+    let mut game_over = false;
+    let mut reconnecting = false;
+    while !game_over {
+        if reconnecting {
+            if backoff.failures() >= MAX_GAME_RECONNECTS {
+                println!("Giving up on game {}", game_id);
+                return;
+            }
+            backoff.wait().await;
+        }
+        reconnecting = true;
+        let mut stream = match client.stream_game(&game_id).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Could not open game {}: {}", game_id, e);
+                continue;
+            }
+        };
+        loop {
+            // Wake up for the absence timers even when the stream is quiet
+            let next = match absence.deadline() {
+                Some(at) => tokio::time::timeout_at(at.into(), stream.next::<GameEvent>())
+                    .await
+                    .ok(),
+                None => Some(stream.next::<GameEvent>().await),
+            };
+            let next = match next {
+                Some(next) => next,
+                None => {
+                    if let Some(action) = absence.due(Instant::now()) {
+                        end_abandoned_game(&client, &game_id, action).await;
                     }
-                    r#""gameState""# => {
-                        let mut board = ChessEngine::default();
-                        for next_move in v["moves"].as_str().unwrap().split_whitespace() {
-                            // println!("{}", next_move);
-                            board = ChessEngine::move_piece(
-                                &board,
-                                ChessMove::from_string(next_move.to_string()),
-                            );
+                    continue;
+                }
+            };
+            let event = match next {
+                Ok(Some(event)) => event,
+                Ok(None) => {
+                    if !game_over {
+                        println!("Game {} stream closed, reconnecting", game_id);
+                    }
+                    break;
+                }
+                Err(e) => {
+                    println!("Game {} stream failed, reconnecting: {}", game_id, e);
+                    break;
+                }
+            };
+            backoff.reset();
+            let state = match event {
+                GameEvent::GameFull(game) => {
+                    let game = *game;
+                    bot_team = game.color_of(&bot_id);
+                    if bot_team.is_none() {
+                        println!("{} is not playing in {}, only watching", bot_id, game_id);
+                    } else {
+                        match prepare_engine_in_background(engine.take(), settings.clone()).await {
+                            Ok(prepared) => engine = Some(prepared),
+                            Err(e) => {
+                                println!("Engine failed to start in {}: {}", game_id, e);
+                                return;
+                            }
                         }
-                        println!("{}", board);
-                        if board.next_to_move() == bot_team {
-                            let bot_move = ChessEngine::find_next_move(&board, 2);
-                            thread::sleep(Duration::from_millis(100));
-                            let auth_header_value = format!("Bearer {}", lichess_api_token);
-                            let client = reqwest::Client::builder().build().unwrap();
-                            let endpoint = format!(
-                                "https://lichess.org/api/bot/game/{}/move/{}",
-                                game_id, bot_move
-                            );
-                            let _res = client
-                                .post(&endpoint)
-                                .header(header::AUTHORIZATION, auth_header_value)
-                                .send()
-                                .await
-                                .unwrap();
+                    }
+                    rated = game.rated;
+                    human_opponent = match bot_team {
+                        Some(Color::White) => game.black.is_human(),
+                        Some(Color::Black) => game.white.is_human(),
+                        None => false,
+                    };
+                    start_fen = game.initial_fen.filter(|fen| fen != "startpos");
+                    println!("{}", game.state.moves);
+                    game.state
+                }
+                GameEvent::GameState(state) => state,
+                GameEvent::ChatLine(line) => {
+                    // Only games the bot plays have an engine to report on
+                    if let Some(engine) = engine.as_ref() {
+                        let search = engine.last_search();
+                        let answer =
+                            chat.reply(&line, engine.name(), search.as_ref(), Instant::now());
+                        if let Some(answer) = answer {
+                            say(&client, &game_id, &line.room, &answer).await;
                         }
                     }
-                    _ => {}
+                    continue;
+                }
+                GameEvent::OpponentGone(gone) => {
+                    if bot_team.is_some() && !game_over {
+                        absence.opponent_gone(gone.gone, gone.claim_win_in_seconds, Instant::now());
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            let running = matches!(state.status.as_str(), "created" | "started");
+            if bot_team.is_some() {
+                if running && !greeted {
+                    greeted = true;
+                    say_to_all(&client, &game_id, &settings.chat.greeting).await;
+                } else if !running && !game_over && greeted {
+                    say_to_all(&client, &game_id, &settings.chat.goodbye).await;
+                }
+            }
+            game_over = !running;
+            let (board, moves) = match replay(&start_fen, &state.moves) {
+                Ok(replayed) => replayed,
+                Err(e) => {
+                    println!("Cannot follow game {}, abandoning it: {}", game_id, e);
+                    return;
+                }
+            };
+            // The board is rebuilt from the whole list, so only the bookkeeping
+            // about earlier moves needs to follow a takeback
+            if moves.len() < plies {
+                println!(
+                    "Moves taken back in {}, {} plies left",
+                    game_id,
+                    moves.len()
+                );
+                adjudicator.reset();
+            }
+            plies = moves.len();
+            println!("{}", board);
+            let bot_team = match bot_team {
+                Some(color) => color,
+                None => continue,
+            };
+            // The ply at which the opponent makes their first move
+            let first_to_move = if moves.len().is_multiple_of(2) {
+                board.next_to_move()
+            } else {
+                !board.next_to_move()
+            };
+            let opponent_first_ply = if first_to_move == bot_team { 1 } else { 0 };
+            absence.update(running, moves.len() == opponent_first_ply, Instant::now());
+            let draw_offered = match bot_team {
+                Color::White => state.bdraw,
+                Color::Black => state.wdraw,
+            };
+            if !draw_offered {
+                draw_answered = false;
+            } else if running && !draw_answered {
+                draw_answered = true;
+                let accept = adjudicator.accepts_draw() || is_tablebase_draw(tablebase, &board);
+                println!(
+                    "{} draw offer in {}",
+                    if accept { "Accepting" } else { "Declining" },
+                    game_id
+                );
+                if let Err(e) = client.answer_draw(&game_id, accept).await {
+                    println!("Failed to answer draw offer in {}: {}", game_id, e);
+                } else if accept {
+                    continue;
+                }
+            }
+            let takeback_requested = match bot_team {
+                Color::White => state.btakeback,
+                Color::Black => state.wtakeback,
+            };
+            if !takeback_requested {
+                takeback_answered = false;
+            } else if running && !takeback_answered {
+                takeback_answered = true;
+                let accept = settings
+                    .takebacks
+                    .allows(rated, human_opponent, takebacks_granted);
+                println!(
+                    "{} takeback in {}",
+                    if accept { "Granting" } else { "Refusing" },
+                    game_id
+                );
+                match client.answer_takeback(&game_id, accept).await {
+                    // The shortened move list follows in the next state
+                    Ok(()) if accept => {
+                        takebacks_granted += 1;
+                        continue;
+                    }
+                    Ok(()) => (),
+                    Err(e) => println!("Failed to answer takeback in {}: {}", game_id, e),
+                }
+            }
+            if state.status == "started" && board.next_to_move() == bot_team {
+                // Set up with the gameFull that named the bot's side
+                let playing = match engine.take() {
+                    Some(engine) => engine,
+                    None => continue,
+                };
+                let budget = move_time_budget(&state, bot_team);
+                let search = choose_move_in_background(
+                    playing,
+                    board.clone(),
+                    start_fen.clone(),
+                    moves.clone(),
+                    settings.clone(),
+                    budget,
+                )
+                .await;
+                let bot_move = match search {
+                    Ok((searched, bot_move)) => {
+                        engine = Some(searched);
+                        bot_move
+                    }
+                    Err(e) => {
+                        println!("Search failed in {}: {}", game_id, e);
+                        return;
+                    }
+                };
+                let after = ChessEngine::move_piece(&board, bot_move);
+                adjudicator.record(
+                    engine
+                        .as_ref()
+                        .and_then(|engine| engine.last_search())
+                        .map(|info| info.score),
+                    (moves.len() / 2 + 1) as u32,
+                    is_tablebase_draw(tablebase, &after),
+                );
+                if adjudicator.should_resign() {
+                    println!("Resigning {}", game_id);
+                    match client.resign(&game_id).await {
+                        Ok(()) => continue,
+                        Err(e) => println!("Failed to resign {}: {}", game_id, e),
+                    }
+                }
+                match post_move(&client, &game_id, bot_move).await {
+                    Ok(()) => (),
+                    // No state follows a move Lichess never got, so the bot
+                    // would sit on its turn. A fresh stream starts with the
+                    // current position and the move is chosen again.
+                    Err(e) if e.is_transient() => {
+                        println!("Move lost in {}, reopening the game", game_id);
+                        break;
+                    }
+                    Err(_) => continue,
+                }
+                if adjudicator.should_offer_draw() {
+                    println!("Offering a draw in {}", game_id);
+                    if let Err(e) = client.answer_draw(&game_id, true).await {
+                        println!("Failed to offer a draw in {}: {}", game_id, e);
+                    }
                 }
             }
-            Err(_) => (),
+        }
+    }
+}
+
+// Accepts a challenge that already holds a slot. When Lichess refuses (the
+// challenger may have withdrawn it) the slot goes to the next queued one.
+async fn accept_challenge(client: &LichessClient, slots: &mut GameSlots, challenge_id: String) {
+    let mut next = Some(challenge_id);
+    while let Some(challenge_id) = next {
+        next = match client.accept_challenge(&challenge_id).await {
+            Ok(()) => None,
+            Err(e) => {
+                println!("Failed to accept challenge {}: {}", challenge_id, e);
+                slots.release(&challenge_id)
+            }
         };
-        buf.clear();
     }
 }
 
+// Frees the slot `id` held and accepts the queued challenge that takes it
+async fn release_slot(client: &LichessClient, slots: &mut GameSlots, id: &str) {
+    if let Some(next) = slots.release(id) {
+        println!("Accepting queued challenge {}", next);
+        accept_challenge(client, slots, next).await;
+    }
+}
+
+// An event stream open this long counts as working even without events,
+// one that drops sooner keeps backing off
+const STABLE_EVENT_STREAM: Duration = Duration::from_secs(60);
+
 async fn subscribe() {
-    let mut stream = connect_tls_stream().unwrap();
     let lichess_api_token = std::env::var("lichess_api_token").unwrap_or("NA".to_string());
-    let stream_event_msg = format!(
-        "GET /api/stream/event HTTP/1.1\nHost: lichess.org\nUser-Agent: curl/7.68.0\nAccept: */*\nAuthorization: Bearer {}\n\n", 
-    lichess_api_token);
-    send_msg(&mut stream, &stream_event_msg);
-
-    let mut stream = BufStream::new(&mut stream);
-    let mut buf = String::new();
-    while stream.read_line(&mut buf).unwrap_or(0) > 0 {
-        match try_parse_json(&buf) {
-            Ok(v) => {
-                let msg_type = v["type"].to_string();
-                match msg_type.as_ref() {
-                    r#""challenge""# => {
-                        let challenge_id = v["challenge"]["id"].as_str().unwrap().to_owned();
-                        let auth_header_value = format!("Bearer {}", lichess_api_token);
-                        let client = reqwest::Client::builder().build().unwrap();
-                        let endpoint =
-                            format!("https://lichess.org/api/challenge/{}/accept", challenge_id);
-                        let _res = client
-                            .post(&endpoint)
-                            .header(header::AUTHORIZATION, auth_header_value)
-                            .send()
-                            .await
-                            .unwrap();
+    let base_url =
+        std::env::var("lichess_base_url").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+    let client = match LichessClient::new(&base_url, &lichess_api_token) {
+        Ok(client) => client,
+        Err(e) => {
+            println!("Could not create HTTP client: {}", e);
+            return;
+        }
+    };
+    let account = match client.account().await {
+        Ok(account) => account,
+        Err(e) => {
+            println!("Could not fetch the bot account: {}", e);
+            return;
+        }
+    };
+    println!("Playing as {}", account.username);
+    let bot_id = Arc::new(account.id);
+    let policy = ChallengePolicy::from_vars(|name| std::env::var(name).ok());
+    let mut slots = GameSlots::from_vars(|name| std::env::var(name).ok());
+
+    let settings = Arc::new(GameSettings {
+        book: load_book(),
+        tablebase: load_tablebase(),
+        chat: ChatConfig::from_vars(|name| std::env::var(name).ok()),
+        adjudication: AdjudicationConfig::from_vars(|name| std::env::var(name).ok()),
+        takebacks: TakebackPolicy::from_vars(|name| std::env::var(name).ok()),
+        absence: AbsencePolicy::from_vars(|name| std::env::var(name).ok()),
+    });
+    // Game tasks by id, a reconnected event stream announces running games again
+    let mut games: HashMap<String, JoinHandle<()>> = HashMap::new();
+    // Game tasks send their id when they end, so the slot is freed even when
+    // the gameFinish event was lost while the event stream was down
+    let (finished_sender, mut finished) = mpsc::unbounded_channel::<String>();
+    let mut backoff = Backoff::default();
+    let mut reconnecting = false;
+    loop {
+        if reconnecting {
+            backoff.wait().await;
+        }
+        reconnecting = true;
+        let mut stream = match client.stream_events().await {
+            Ok(stream) => stream,
+            Err(e) if e.is_unauthorized() => {
+                println!("Lichess refused the token, stopping: {}", e);
+                return;
+            }
+            Err(e) => {
+                println!("Could not open event stream: {}", e);
+                continue;
+            }
+        };
+        let opened = Instant::now();
+        loop {
+            let next = tokio::select! {
+                next = stream.next::<Event>() => next,
+                Some(game_id) = finished.recv() => {
+                    games.remove(&game_id);
+                    release_slot(&client, &mut slots, &game_id).await;
+                    continue;
+                }
+            };
+            let event = match next {
+                Ok(Some(event)) => event,
+                Ok(None) => {
+                    println!("Event stream closed, reconnecting");
+                    break;
+                }
+                Err(e) => {
+                    println!("Event stream failed, reconnecting: {}", e);
+                    break;
+                }
+            };
+            backoff.reset();
+            match event {
+                Event::Challenge { challenge } => {
+                    let challenger = challenge.challenger.as_ref().map(|c| c.id.as_str());
+                    // Challenges the bot sent itself show up in the stream too
+                    if challenger.is_some_and(|id| id.eq_ignore_ascii_case(&bot_id)) {
+                        continue;
                     }
-                    r#""gameStart""# => {
-                        let game_id = v["game"]["id"].as_str().unwrap().to_owned();
-                        tokio::spawn(async move { play_game(game_id).await });
+                    let admission = policy
+                        .evaluate(&challenge)
+                        .and_then(|()| slots.admit(&challenge.id));
+                    match admission {
+                        Ok(Admission::Accept) => {
+                            accept_challenge(&client, &mut slots, challenge.id).await
+                        }
+                        Ok(Admission::Queue) => println!("Queued challenge {}", challenge.id),
+                        Err(reason) => {
+                            println!(
+                                "Declining challenge {} from {}: {}",
+                                challenge.id,
+                                challenger.unwrap_or("?"),
+                                reason.key()
+                            );
+                            if let Err(e) =
+                                client.decline_challenge(&challenge.id, reason.key()).await
+                            {
+                                println!("Failed to decline challenge {}: {}", challenge.id, e);
+                            }
+                        }
                     }
-                    _ => (),
                 }
+                Event::ChallengeCanceled { challenge } => {
+                    release_slot(&client, &mut slots, &challenge.id).await
+                }
+                Event::GameStart { game } => {
+                    slots.game_started(&game.id);
+                    games.retain(|_, task| !task.is_finished());
+                    if games.contains_key(&game.id) {
+                        continue;
+                    }
+                    let client = client.clone();
+                    let bot_id = bot_id.clone();
+                    let settings = settings.clone();
+                    let finished_sender = finished_sender.clone();
+                    let game_id = game.id.clone();
+                    let task = tokio::spawn(async move {
+                        play_game(client, bot_id, game_id.clone(), settings).await;
+                        let _ = finished_sender.send(game_id);
+                    });
+                    games.insert(game.id, task);
+                }
+                Event::GameFinish { game } => {
+                    println!("Game {} finished", game.id);
+                    release_slot(&client, &mut slots, &game.id).await;
+                }
+                _ => (),
             }
-            Err(_) => (),
-        };
-        buf.clear();
+        }
+        // A quiet stream only carries keep-alives, staying up is success enough
+        if opened.elapsed() >= STABLE_EVENT_STREAM {
+            backoff.reset();
+        }
     }
 }
 
 #[tokio::main]
 async fn main() {
+    if std::env::args().any(|arg| arg == "--uci") {
+        uci::run();
+        return;
+    }
+    if std::env::args().any(|arg| arg == "--xboard") {
+        let tablebase = load_tablebase();
+        let engine = create_engine(&tablebase);
+        xboard::run(
+            engine,
+            Box::new(move || Box::new(builtin_engine(&tablebase))),
+        );
+        return;
+    }
     subscribe().await;
 }
@@ -0,0 +1,87 @@
+// Which takeback requests from the opponent the bot grants
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TakebackMode {
+    Never,
+    Casual,
+    Humans,
+    Always,
+}
+
+impl TakebackMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "never" => Some(TakebackMode::Never),
+            "casual" => Some(TakebackMode::Casual),
+            "humans" => Some(TakebackMode::Humans),
+            "always" => Some(TakebackMode::Always),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TakebackPolicy {
+    pub mode: TakebackMode,
+    // Takebacks granted per game at most
+    pub max_per_game: u32,
+}
+
+impl Default for TakebackPolicy {
+    fn default() -> Self {
+        TakebackPolicy {
+            mode: TakebackMode::Never,
+            max_per_game: 1,
+        }
+    }
+}
+
+impl TakebackPolicy {
+    // `lichess_takebacks` (never, casual, humans or always) and `lichess_takeback_max`
+    pub fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Self {
+        let defaults = TakebackPolicy::default();
+        TakebackPolicy {
+            mode: var("lichess_takebacks")
+                .and_then(|name| TakebackMode::from_name(name.trim()))
+                .unwrap_or(defaults.mode),
+            max_per_game: var("lichess_takeback_max")
+                .and_then(|max| max.trim().parse().ok())
+                .unwrap_or(defaults.max_per_game),
+        }
+    }
+
+    pub fn allows(&self, rated: bool, human_opponent: bool, granted: u32) -> bool {
+        let mode_allows = match self.mode {
+            TakebackMode::Never => false,
+            TakebackMode::Casual => !rated,
+            TakebackMode::Humans => human_opponent,
+            TakebackMode::Always => true,
+        };
+        mode_allows && granted < self.max_per_game
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants_takebacks_by_mode_up_to_the_limit() {
+        let policy = |mode| TakebackPolicy {
+            mode,
+            max_per_game: 2,
+        };
+        assert!(!policy(TakebackMode::Never).allows(false, true, 0));
+        assert!(policy(TakebackMode::Casual).allows(false, false, 0));
+        assert!(!policy(TakebackMode::Casual).allows(true, true, 0));
+        assert!(policy(TakebackMode::Humans).allows(true, true, 1));
+        assert!(!policy(TakebackMode::Humans).allows(false, false, 0));
+        assert!(!policy(TakebackMode::Always).allows(false, true, 2));
+
+        let configured = TakebackPolicy::from_vars(|name| match name {
+            "lichess_takebacks" => Some("casual".to_string()),
+            _ => None,
+        });
+        assert_eq!(configured.mode, TakebackMode::Casual);
+        assert_eq!(configured.max_per_game, 1);
+    }
+}
@@ -0,0 +1,422 @@
+use crate::chess_bitboard::allocate_time;
+use crate::chess_bitboard::evaluate;
+use crate::chess_bitboard::ChessEngine;
+use crate::chess_bitboard::Searcher;
+use crate::chess_bitboard::Tablebase;
+use crate::chess_bitboard::MAX_DEPTH;
+use chess::Board;
+use chess::ChessMove;
+use chess::Color;
+use std::io::BufRead;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+// Reported to GUIs by both the UCI and the xboard front ends
+pub const ENGINE_NAME: &str = "gambinobot";
+const DEFAULT_HASH_MB: usize = 16;
+const MAX_HASH_MB: usize = 4096;
+const MAX_CONTEMPT: i32 = 200;
+
+// Speaks UCI over stdin/stdout until `quit` or end of input
+pub fn run() {
+    let mut uci = Uci::new();
+    read_commands(|line| uci.handle(line));
+    uci.stop();
+}
+
+// Hands each trimmed line of stdin to `handle` until it returns false or
+// the input ends
+pub fn read_commands<F: FnMut(&str) -> bool>(mut handle: F) {
+    let stdin = std::io::stdin();
+    for line in stdin.lock().lines() {
+        match line {
+            Ok(line) if handle(line.trim()) => {}
+            _ => break,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct GoParams {
+    wtime: Option<u64>,
+    btime: Option<u64>,
+    winc: Option<u64>,
+    binc: Option<u64>,
+    movetime: Option<u64>,
+    depth: Option<isize>,
+    infinite: bool,
+}
+
+impl GoParams {
+    fn parse(args: &[&str]) -> Self {
+        let mut params = GoParams::default();
+        let mut tokens = args.iter();
+        while let Some(token) = tokens.next() {
+            let mut value = || tokens.next().and_then(|v| v.parse::<i64>().ok());
+            match *token {
+                "wtime" => params.wtime = value().map(|v| v.max(0) as u64),
+                "btime" => params.btime = value().map(|v| v.max(0) as u64),
+                "winc" => params.winc = value().map(|v| v.max(0) as u64),
+                "binc" => params.binc = value().map(|v| v.max(0) as u64),
+                "movetime" => params.movetime = value().map(|v| v.max(0) as u64),
+                "depth" => params.depth = value().map(|v| v.max(1) as isize),
+                "infinite" => params.infinite = true,
+                _ => {}
+            }
+        }
+        params
+    }
+
+    // None means search until told to stop or the depth limit is reached
+    fn budget(&self, side: Color) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+        if let Some(movetime) = self.movetime {
+            return Some(Duration::from_millis(movetime));
+        }
+        let (time, inc) = match side {
+            Color::White => (self.wtime, self.winc),
+            Color::Black => (self.btime, self.binc),
+        };
+        time.map(|remaining| {
+            allocate_time(
+                Duration::from_millis(remaining),
+                Duration::from_millis(inc.unwrap_or(0)),
+            )
+        })
+    }
+
+    // A bare `go` searches until `stop`, like `go infinite`
+    fn waits_for_stop(&self) -> bool {
+        self.infinite
+            || (self.movetime.is_none()
+                && self.wtime.is_none()
+                && self.btime.is_none()
+                && self.depth.is_none())
+    }
+}
+
+// `position [startpos | fen <fen>] [moves <move>...]`
+fn parse_position(args: &[&str]) -> Result<ChessEngine, String> {
+    let moves_at = args.iter().position(|a| *a == "moves");
+    let (setup, moves) = match moves_at {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (args, &args[args.len()..]),
+    };
+    let mut engine = match setup.split_first() {
+        Some((&"startpos", _)) => ChessEngine::default(),
+        Some((&"fen", fen)) => {
+            let fen = fen.join(" ");
+            Board::from_str(&fen).map_err(|e| format!("invalid fen {}: {}", fen, e))?;
+            ChessEngine::from_fen(fen)
+        }
+        _ => return Err("expected startpos or fen".to_string()),
+    };
+    for m in moves {
+        let chess_move = ChessMove::from_str(m)
+            .ok()
+            .filter(|chess_move| engine.bitboard.legal(*chess_move))
+            .ok_or_else(|| format!("illegal move {}", m))?;
+        engine = ChessEngine::move_piece(&engine, chess_move);
+    }
+    Ok(engine)
+}
+
+// Centipawns from White's side, the way GUIs show evaluations
+fn static_eval(engine: &ChessEngine) -> i32 {
+    match engine.next_to_move() {
+        Color::White => evaluate(engine),
+        Color::Black => -evaluate(engine),
+    }
+}
+
+// `setoption name <name> [value <value>]`, names may contain spaces
+fn parse_option(args: &[&str]) -> Option<(String, String)> {
+    let value_at = args.iter().position(|a| *a == "value");
+    let (name, value) = match value_at {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (args, &args[args.len()..]),
+    };
+    match name.split_first() {
+        Some((&"name", name)) if !name.is_empty() => Some((name.join(" "), value.join(" "))),
+        _ => None,
+    }
+}
+
+struct Uci {
+    engine: ChessEngine,
+    // Taken by the search thread while it runs and handed back when it finishes
+    searcher: Option<Searcher>,
+    search: Option<JoinHandle<Searcher>>,
+    stop: Arc<AtomicBool>,
+    contempt: i32,
+    tablebase: Option<Arc<Tablebase>>,
+}
+
+impl Uci {
+    fn new() -> Self {
+        let mut uci = Uci {
+            engine: ChessEngine::default(),
+            searcher: None,
+            search: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            contempt: 0,
+            tablebase: None,
+        };
+        uci.searcher = Some(uci.build_searcher(DEFAULT_HASH_MB));
+        uci
+    }
+
+    fn build_searcher(&self, hash_mb: usize) -> Searcher {
+        let mut searcher = Searcher::with_hash_size(hash_mb);
+        searcher.set_stop_signal(self.stop.clone());
+        searcher.set_contempt(self.contempt);
+        if let Some(tablebase) = self.tablebase.as_ref() {
+            searcher.set_tablebase(tablebase.clone());
+        }
+        searcher
+    }
+
+    // Returns false once the GUI asks us to quit
+    fn handle(&mut self, line: &str) -> bool {
+        let tokens = line.split_whitespace().collect::<Vec<&str>>();
+        let (command, args) = match tokens.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return true,
+        };
+        match command {
+            "uci" => {
+                println!("id name {}", ENGINE_NAME);
+                println!("id author sean");
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_HASH_MB, MAX_HASH_MB
+                );
+                println!(
+                    "option name Contempt type spin default 0 min -{} max {}",
+                    MAX_CONTEMPT, MAX_CONTEMPT
+                );
+                println!("option name SyzygyPath type string default <empty>");
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "ucinewgame" => {
+                self.stop();
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.new_game();
+                }
+                self.engine = ChessEngine::default();
+            }
+            "position" => {
+                self.stop();
+                match parse_position(args) {
+                    Ok(engine) => self.engine = engine,
+                    Err(e) => println!("info string {}", e),
+                }
+            }
+            "setoption" => {
+                self.stop();
+                match parse_option(args) {
+                    Some((name, value)) => self.set_option(&name, &value),
+                    None => println!("info string malformed setoption"),
+                }
+            }
+            "go" => {
+                self.stop();
+                self.go(GoParams::parse(args));
+            }
+            "stop" => self.stop(),
+            // Not part of UCI, prints the static evaluation as Stockfish's `eval` does
+            "eval" => println!("info string static eval {}", static_eval(&self.engine)),
+            "quit" => return false,
+            _ => println!("info string unknown command {}", command),
+        }
+        true
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) {
+        match name.to_lowercase().as_str() {
+            "hash" => match value.parse::<usize>() {
+                Ok(mb) => self.searcher = Some(self.build_searcher(mb.clamp(1, MAX_HASH_MB))),
+                Err(_) => println!("info string invalid hash size {}", value),
+            },
+            "contempt" => match value.parse::<i32>() {
+                Ok(cp) => {
+                    self.contempt = cp.clamp(-MAX_CONTEMPT, MAX_CONTEMPT);
+                    if let Some(searcher) = self.searcher.as_mut() {
+                        searcher.set_contempt(self.contempt);
+                    }
+                }
+                Err(_) => println!("info string invalid contempt {}", value),
+            },
+            "syzygypath" => {
+                if value.is_empty() || value == "<empty>" {
+                    return;
+                }
+                // The prober can only be initialised once per process
+                if self.tablebase.is_some() {
+                    println!("info string tablebases are already loaded");
+                    return;
+                }
+                match Tablebase::open(value) {
+                    Ok(tablebase) => {
+                        println!(
+                            "info string loaded tablebases up to {} pieces",
+                            tablebase.max_pieces()
+                        );
+                        let tablebase = Arc::new(tablebase);
+                        if let Some(searcher) = self.searcher.as_mut() {
+                            searcher.set_tablebase(tablebase.clone());
+                        }
+                        self.tablebase = Some(tablebase);
+                    }
+                    Err(e) => println!("info string {}", e),
+                }
+            }
+            _ => println!("info string unknown option {}", name),
+        }
+    }
+
+    fn go(&mut self, params: GoParams) {
+        let mut searcher = match self.searcher.take() {
+            Some(searcher) => searcher,
+            None => return,
+        };
+        self.stop.store(false, Ordering::SeqCst);
+        let stop = self.stop.clone();
+        let engine = self.engine.clone();
+        let budget = params.budget(engine.next_to_move());
+        let depth = params.depth.unwrap_or(MAX_DEPTH);
+        let waits_for_stop = params.waits_for_stop();
+        self.search = Some(thread::spawn(move || {
+            let start = Instant::now();
+            let result = searcher.iterative_deepening(&engine, depth, budget);
+            let score = match result.mate_in() {
+                Some(moves) => format!("mate {}", moves),
+                None => format!("cp {}", result.score),
+            };
+            let pv = result
+                .pv
+                .iter()
+                .map(|m| m.to_string())
+                .collect::<Vec<String>>()
+                .join(" ");
+            println!(
+                "info depth {} score {} nodes {} time {} pv {}",
+                result.depth,
+                score,
+                result.nodes,
+                start.elapsed().as_millis(),
+                pv
+            );
+            // bestmove may not be sent before `stop` in infinite mode
+            while waits_for_stop && !stop.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            match result.best_move {
+                Some(m) => println!("bestmove {}", m),
+                None => println!("bestmove 0000"),
+            }
+            searcher
+        }));
+    }
+
+    // Waits for a running search to finish
+    fn wait(&mut self) {
+        if let Some(search) = self.search.take() {
+            if let Ok(searcher) = search.join() {
+                self.searcher = Some(searcher);
+            }
+        }
+        if self.searcher.is_none() {
+            self.searcher = Some(self.build_searcher(DEFAULT_HASH_MB));
+        }
+    }
+
+    // Ends a running search early, an infinite one would never finish by itself
+    fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(line: &str) -> Vec<&str> {
+        line.split_whitespace().collect()
+    }
+
+    #[test]
+    fn parses_startpos_and_fen_positions() {
+        let engine = parse_position(&tokens("startpos moves e2e4 e7e5 g1f3")).unwrap();
+        assert_eq!(engine.history.len(), 3);
+        assert_eq!(engine.next_to_move(), Color::Black);
+
+        let engine =
+            parse_position(&tokens("fen 4k3/P7/8/8/8/8/8/4K3 w - - 3 40 moves a7a8q")).unwrap();
+        assert_eq!(engine.halfmove_clock, 0);
+        assert!(parse_position(&tokens("startpos moves e2e5")).is_err());
+        assert!(parse_position(&tokens("fen not a fen")).is_err());
+    }
+
+    #[test]
+    fn parses_go_limits() {
+        let params = GoParams::parse(&tokens("wtime 60000 btime 30000 winc 1000 binc 0"));
+        assert_eq!(params.wtime, Some(60000));
+        assert!(params.budget(Color::Black).unwrap() < params.budget(Color::White).unwrap());
+        assert!(!params.waits_for_stop());
+
+        let params = GoParams::parse(&tokens("movetime 250 depth 6"));
+        assert_eq!(
+            params.budget(Color::White),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(params.depth, Some(6));
+
+        assert!(GoParams::parse(&tokens("infinite")).waits_for_stop());
+        assert!(GoParams::parse(&[]).budget(Color::White).is_none());
+    }
+
+    #[test]
+    fn parses_option_names_with_spaces() {
+        assert_eq!(
+            parse_option(&tokens("name Syzygy Path value /tb/a:/tb/b")),
+            Some(("Syzygy Path".to_string(), "/tb/a:/tb/b".to_string()))
+        );
+        assert_eq!(
+            parse_option(&tokens("name Clear Hash")),
+            Some(("Clear Hash".to_string(), String::new()))
+        );
+        assert_eq!(parse_option(&tokens("value 3")), None);
+    }
+
+    #[test]
+    fn new_position_stops_an_infinite_search() {
+        let mut uci = Uci::new();
+        uci.handle("go infinite");
+        uci.handle("position startpos moves e2e4");
+        assert!(uci.search.is_none());
+        assert_eq!(uci.engine.history.len(), 1);
+        uci.handle("go infinite");
+        uci.handle("ucinewgame");
+        assert!(uci.search.is_none());
+        assert!(uci.searcher.is_some());
+    }
+
+    #[test]
+    fn static_eval_is_from_whites_side() {
+        let white = parse_position(&tokens("fen 4k3/8/8/8/8/8/8/3QK3 w - - 0 1")).unwrap();
+        let black = parse_position(&tokens("fen 4k3/8/8/8/8/8/8/3QK3 b - - 0 1")).unwrap();
+        assert!(static_eval(&white) > 800);
+        assert_eq!(static_eval(&white), static_eval(&black));
+    }
+}
@@ -0,0 +1,358 @@
+use crate::chess_bitboard::allocate_time;
+use crate::chess_bitboard::ChessEngine;
+use crate::engine::Engine;
+use crate::uci::read_commands;
+use crate::uci::ENGINE_NAME;
+use chess::Board;
+use chess::BoardStatus;
+use chess::ChessMove;
+use chess::Color;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Used until the GUI sends a time control
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(5);
+
+// Builds the built-in engine that takes over when the configured one fails
+pub type EngineFallback = Box<dyn Fn() -> Box<dyn Engine>>;
+
+// Speaks CECP (xboard/WinBoard protocol version 2) over stdin/stdout until
+// `quit` or end of input
+pub fn run(engine: Box<dyn Engine>, fallback: EngineFallback) {
+    let mut xboard = Xboard::new(engine, fallback);
+    read_commands(|line| xboard.handle(line));
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct TimeControl {
+    // Fixed time per move from `st`
+    per_move: Option<Duration>,
+    increment: Duration,
+    // Our remaining time from `time`
+    own: Option<Duration>,
+}
+
+impl TimeControl {
+    fn budget(&self) -> Duration {
+        if let Some(per_move) = self.per_move {
+            return per_move;
+        }
+        match self.own {
+            Some(remaining) => allocate_time(remaining, self.increment),
+            None => DEFAULT_MOVE_TIME,
+        }
+    }
+}
+
+// `level MPS BASE INC`, BASE is minutes or minutes:seconds and INC is seconds
+fn parse_level(args: &[&str]) -> Option<(u32, Duration, Duration)> {
+    if args.len() != 3 {
+        return None;
+    }
+    let moves = args[0].parse().ok()?;
+    let mut base = args[1].splitn(2, ':');
+    let minutes: u64 = base.next()?.parse().ok()?;
+    let seconds: u64 = match base.next() {
+        Some(seconds) => seconds.parse().ok()?,
+        None => 0,
+    };
+    let increment: f64 = args[2].parse().ok()?;
+    Some((
+        moves,
+        Duration::from_secs(minutes * 60 + seconds),
+        Duration::from_millis((increment.max(0.0) * 1000.0) as u64),
+    ))
+}
+
+// `time` and `otim` are in centiseconds
+fn parse_centiseconds(arg: Option<&&str>) -> Option<Duration> {
+    arg.and_then(|cs| cs.parse::<i64>().ok())
+        .map(|cs| Duration::from_millis(cs.max(0) as u64 * 10))
+}
+
+struct Xboard {
+    engine: Box<dyn Engine>,
+    fallback: EngineFallback,
+    start_fen: Option<String>,
+    moves: Vec<ChessMove>,
+    board: ChessEngine,
+    // In force mode the engine only records moves, it never plays them
+    force: bool,
+    engine_side: Color,
+    clock: TimeControl,
+}
+
+impl Xboard {
+    fn new(engine: Box<dyn Engine>, fallback: EngineFallback) -> Self {
+        Xboard {
+            engine,
+            fallback,
+            start_fen: None,
+            moves: Vec::new(),
+            board: ChessEngine::default(),
+            force: false,
+            engine_side: Color::Black,
+            clock: TimeControl::default(),
+        }
+    }
+
+    // Returns false once the GUI asks us to quit
+    fn handle(&mut self, line: &str) -> bool {
+        let tokens = line.split_whitespace().collect::<Vec<&str>>();
+        let (command, args) = match tokens.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return true,
+        };
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "post" | "nopost" | "hard"
+            | "easy" | "computer" => {}
+            "protover" => println!(
+                "feature myname=\"{}\" usermove=1 setboard=1 ping=1 colors=0 sigint=0 sigterm=0 analyze=0 reuse=1 done=1",
+                ENGINE_NAME
+            ),
+            "ping" => println!("pong {}", args.first().unwrap_or(&"")),
+            "new" => {
+                self.set_position(None);
+                self.force = false;
+                self.engine_side = Color::Black;
+                if let Err(e) = self.engine.new_game() {
+                    self.replace_engine(&e);
+                }
+            }
+            "setboard" => {
+                let fen = args.join(" ");
+                match Board::from_str(&fen) {
+                    Ok(_) => self.set_position(Some(fen)),
+                    Err(_) => println!("tellusererror Illegal position"),
+                }
+            }
+            "force" | "result" => self.force = true,
+            "go" => {
+                self.force = false;
+                self.engine_side = self.board.next_to_move();
+                self.think();
+            }
+            "playother" => {
+                self.force = false;
+                self.engine_side = !self.board.next_to_move();
+            }
+            "usermove" => match args.first() {
+                Some(m) => self.user_move(m),
+                None => println!("Error (missing move): usermove"),
+            },
+            "level" => match parse_level(args) {
+                Some((_, base, increment)) => {
+                    self.clock.per_move = None;
+                    self.clock.increment = increment;
+                    self.clock.own = Some(base);
+                }
+                None => println!("Error (bad time control): {}", line),
+            },
+            "st" => match args.first().and_then(|s| s.parse::<u64>().ok()) {
+                Some(seconds) => self.clock.per_move = Some(Duration::from_secs(seconds)),
+                None => println!("Error (bad time control): {}", line),
+            },
+            "time" => self.clock.own = parse_centiseconds(args.first()).or(self.clock.own),
+            // The opponent's clock does not change how long we think
+            "otim" => {}
+            "quit" => return false,
+            _ => println!("Error (unknown command): {}", command),
+        }
+        true
+    }
+
+    fn set_position(&mut self, fen: Option<String>) {
+        self.board = match fen.as_ref() {
+            Some(fen) => ChessEngine::from_fen(fen.clone()),
+            None => ChessEngine::default(),
+        };
+        self.start_fen = fen;
+        self.moves.clear();
+    }
+
+    fn play(&mut self, m: ChessMove) {
+        self.board = ChessEngine::move_piece(&self.board, m);
+        self.moves.push(m);
+    }
+
+    fn user_move(&mut self, m: &str) {
+        match ChessMove::from_str(m)
+            .ok()
+            .filter(|chess_move| self.board.bitboard.legal(*chess_move))
+        {
+            Some(chess_move) => {
+                self.play(chess_move);
+                if !self.force && self.board.next_to_move() == self.engine_side {
+                    self.think();
+                }
+            }
+            None => println!("Illegal move: {}", m),
+        }
+    }
+
+    fn think(&mut self) {
+        if self.report_game_end() {
+            return;
+        }
+        let budget = self.clock.budget();
+        let result =
+            self.engine
+                .choose_move(&self.board, self.start_fen.as_deref(), &self.moves, budget);
+        let m = match result {
+            Ok(m) => m,
+            Err(e) => {
+                self.replace_engine(&e);
+                match self.engine.choose_move(
+                    &self.board,
+                    self.start_fen.as_deref(),
+                    &self.moves,
+                    budget,
+                ) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        println!("Error (no move): {}", e);
+                        return;
+                    }
+                }
+            }
+        };
+        self.play(m);
+        println!("move {}", m);
+        self.report_game_end();
+    }
+
+    // The built-in search takes over when the configured engine fails
+    fn replace_engine(&mut self, error: &str) {
+        println!(
+            "telluser {} failed, switching to built-in: {}",
+            self.engine.name(),
+            error
+        );
+        self.engine = (self.fallback)();
+    }
+
+    fn report_game_end(&self) -> bool {
+        match self.board.bitboard.status() {
+            BoardStatus::Checkmate => {
+                match self.board.next_to_move() {
+                    Color::White => println!("0-1 {{Black mates}}"),
+                    Color::Black => println!("1-0 {{White mates}}"),
+                }
+                true
+            }
+            BoardStatus::Stalemate => {
+                println!("1/2-1/2 {{Stalemate}}");
+                true
+            }
+            BoardStatus::Ongoing => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess::MoveGen;
+
+    // Always plays the first legal move
+    struct FirstMove;
+
+    impl Engine for FirstMove {
+        fn name(&self) -> &str {
+            "first"
+        }
+        fn new_game(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+        fn choose_move(
+            &mut self,
+            board: &ChessEngine,
+            _start_fen: Option<&str>,
+            _moves: &[ChessMove],
+            _budget: Duration,
+        ) -> Result<ChessMove, String> {
+            MoveGen::new_legal(&board.bitboard)
+                .next()
+                .ok_or_else(|| "no moves".to_string())
+        }
+    }
+
+    #[test]
+    fn parses_level_commands() {
+        assert_eq!(
+            parse_level(&["40", "5", "0"]),
+            Some((40, Duration::from_secs(300), Duration::from_secs(0)))
+        );
+        assert_eq!(
+            parse_level(&["0", "2:30", "1.5"]),
+            Some((0, Duration::from_secs(150), Duration::from_millis(1500)))
+        );
+        assert_eq!(parse_level(&["0", "five", "0"]), None);
+    }
+
+    #[test]
+    fn replies_to_user_moves_unless_forced() {
+        let mut xboard = Xboard::new(Box::new(FirstMove), Box::new(|| Box::new(FirstMove)));
+        xboard.handle("new");
+        xboard.handle("usermove e2e4");
+        assert_eq!(xboard.moves.len(), 2);
+        assert_eq!(xboard.board.next_to_move(), Color::White);
+
+        xboard.handle("force");
+        xboard.handle("usermove d2d4");
+        assert_eq!(xboard.moves.len(), 3);
+        xboard.handle("usermove d2d4");
+        assert_eq!(xboard.moves.len(), 3);
+
+        xboard.handle("go");
+        assert_eq!(xboard.moves.len(), 4);
+        assert_eq!(xboard.engine_side, Color::Black);
+    }
+
+    // Fails every search, like an external engine that crashed
+    struct Broken;
+
+    impl Engine for Broken {
+        fn name(&self) -> &str {
+            "broken"
+        }
+        fn new_game(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+        fn choose_move(
+            &mut self,
+            _board: &ChessEngine,
+            _start_fen: Option<&str>,
+            _moves: &[ChessMove],
+            _budget: Duration,
+        ) -> Result<ChessMove, String> {
+            Err("crashed".to_string())
+        }
+    }
+
+    #[test]
+    fn failed_engine_is_replaced_by_the_fallback() {
+        let mut xboard = Xboard::new(Box::new(Broken), Box::new(|| Box::new(FirstMove)));
+        xboard.handle("new");
+        xboard.handle("go");
+        assert_eq!(xboard.moves.len(), 1);
+        assert_eq!(xboard.engine.name(), "first");
+    }
+
+    #[test]
+    fn setboard_and_clock_commands() {
+        let mut xboard = Xboard::new(Box::new(FirstMove), Box::new(|| Box::new(FirstMove)));
+        xboard.handle("setboard 4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert_eq!(
+            xboard.start_fen.as_deref(),
+            Some("4k3/8/8/8/8/8/8/4K2R w K - 0 1")
+        );
+        xboard.handle("st 2");
+        assert_eq!(xboard.clock.budget(), Duration::from_secs(2));
+        xboard.handle("level 0 1 0");
+        xboard.handle("time 500");
+        xboard.handle("otim 6000");
+        assert_eq!(xboard.clock.own, Some(Duration::from_secs(5)));
+        assert!(xboard.clock.budget() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,534 @@
+mod mock_lichess;
+
+use mock_lichess::MockLichess;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+
+// Runs the bot binary against the mock and kills it when the test ends
+struct Bot(Child);
+
+impl Bot {
+    fn start(mock: &MockLichess) -> Self {
+        Bot::start_with(mock, &[])
+    }
+
+    fn start_with(mock: &MockLichess, settings: &[(&str, &str)]) -> Self {
+        let child = Command::new(env!("CARGO_BIN_EXE_lichess_bot_rust"))
+            .env_clear()
+            .env("lichess_base_url", &mock.base_url)
+            .env("lichess_api_token", "test-token")
+            .env("lichess_hash_mb", "1")
+            .envs(settings.iter().copied())
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("bot binary");
+        Bot(child)
+    }
+}
+
+impl Drop for Bot {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+const CHALLENGE: &str = r#"{"type":"challenge","challenge":{"id":"chal1234","status":"created","challenger":{"id":"someone","name":"Someone","rating":1500},"destUser":{"id":"gambinobot","name":"gambinobot","title":"BOT"},"variant":{"key":"standard","name":"Standard"},"rated":false,"speed":"blitz","timeControl":{"type":"clock","limit":180,"increment":0},"color":"random"}}"#;
+const GAME_START: &str = r#"{"type":"gameStart","game":{"gameId":"game1234","id":"game1234","color":"white","isMyTurn":true}}"#;
+const GAME_FULL: &str = r#"{"type":"gameFull","id":"game1234","rated":false,"variant":{"key":"standard"},"speed":"blitz","white":{"id":"gambinobot","name":"gambinobot","title":"BOT"},"black":{"id":"someone","name":"Someone"},"initialFen":"startpos","state":{"type":"gameState","moves":"","wtime":3000,"btime":3000,"winc":0,"binc":0,"status":"started"}}"#;
+
+#[tokio::test]
+async fn accepts_challenge_and_plays_a_game() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start(&mock);
+
+    mock.push_event(CHALLENGE);
+    let accept = mock
+        .wait_for_request("POST /api/challenge/chal1234/accept")
+        .await;
+    assert_eq!(accept.authorization.as_deref(), Some("Bearer test-token"));
+    assert!(accept
+        .user_agent
+        .unwrap_or_default()
+        .starts_with("lichess_bot_rust/"));
+
+    mock.push_event(GAME_START);
+    mock.wait_for_request("GET /api/bot/game/stream/game1234")
+        .await;
+    mock.push_game("game1234", GAME_FULL);
+    let first = mock
+        .wait_for_request("POST /api/bot/game/game1234/move/")
+        .await;
+    let first_move = first.path.rsplit('/').next().unwrap().to_string();
+
+    let reply = format!(
+        r#"{{"type":"gameState","moves":"{} e7e5","wtime":2500,"btime":3000,"winc":0,"binc":0,"status":"started"}}"#,
+        first_move
+    );
+    mock.push_game("game1234", &reply);
+    let moves = mock
+        .wait_for_requests("POST /api/bot/game/game1234/move/", 2)
+        .await;
+    assert_eq!(moves.len(), 2);
+
+    // Nothing is played once the game is over, even on the bot's turn
+    let finished = format!(
+        r#"{{"type":"gameState","moves":"{} e7e5","wtime":2500,"btime":3000,"winc":0,"binc":0,"status":"resign","winner":"black"}}"#,
+        first_move
+    );
+    mock.push_game("game1234", &finished);
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let posted = mock
+        .requests()
+        .iter()
+        .filter(|r| r.path.starts_with("/api/bot/game/game1234/move/"))
+        .count();
+    assert_eq!(posted, 2);
+}
+
+#[tokio::test]
+async fn keeps_running_when_lichess_rejects_a_request() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start(&mock);
+
+    mock.respond(
+        "POST /api/challenge/gone0000/accept",
+        404,
+        r#"{"error":"Challenge not found"}"#,
+    );
+    mock.push_event(&CHALLENGE.replace("chal1234", "gone0000"));
+    mock.wait_for_request("POST /api/challenge/gone0000/accept")
+        .await;
+
+    mock.push_event(CHALLENGE);
+    mock.wait_for_request("POST /api/challenge/chal1234/accept")
+        .await;
+}
+
+#[tokio::test]
+async fn plays_the_side_matching_its_account() {
+    let mock = MockLichess::start().await;
+    mock.respond(
+        "GET /api/account",
+        200,
+        r#"{"id":"otherbot","username":"OtherBot","title":"BOT"}"#,
+    );
+    let _bot = Bot::start(&mock);
+    mock.wait_for_request("GET /api/stream/event").await;
+
+    // Listed as black, with white already on move two
+    mock.push_event(&GAME_START.replace("game1234", "black123"));
+    mock.push_game(
+        "black123",
+        &GAME_FULL
+            .replace("game1234", "black123")
+            .replace(
+                r#""id":"gambinobot","name":"gambinobot""#,
+                r#""id":"human","name":"Human""#,
+            )
+            .replace(
+                r#""id":"someone","name":"Someone""#,
+                r#""id":"OtherBot","name":"OtherBot""#,
+            )
+            .replace(r#""moves":"""#, r#""moves":"e2e4""#),
+    );
+    mock.wait_for_request("POST /api/bot/game/black123/move/")
+        .await;
+
+    // A game between two other players is only watched
+    mock.push_event(&GAME_START.replace("game1234", "watch123"));
+    mock.push_game("watch123", &GAME_FULL.replace("game1234", "watch123"));
+    mock.wait_for_request("GET /api/bot/game/stream/watch123")
+        .await;
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    assert!(!mock
+        .requests()
+        .iter()
+        .any(|r| r.path.starts_with("/api/bot/game/watch123/move/")));
+}
+
+#[tokio::test]
+async fn declines_challenges_outside_its_policy() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start_with(&mock, &[("lichess_challenge_speeds", "rapid,classical")]);
+
+    mock.push_event(CHALLENGE);
+    let decline = mock
+        .wait_for_request("POST /api/challenge/chal1234/decline")
+        .await;
+    assert_eq!(decline.body, "reason=tooFast");
+
+    // Its own outgoing challenges are left alone
+    mock.push_event(&CHALLENGE.replace(
+        r#""id":"someone","name":"Someone""#,
+        r#""id":"gambinobot","name":"GambinoBot""#,
+    ));
+    mock.push_event(&CHALLENGE.replace("chal1234", "next1234"));
+    mock.wait_for_request("POST /api/challenge/next1234/decline")
+        .await;
+    let answered = mock
+        .requests()
+        .iter()
+        .filter(|r| r.path.starts_with("/api/challenge/chal1234/"))
+        .count();
+    assert_eq!(answered, 1);
+}
+
+#[tokio::test]
+async fn queues_challenges_beyond_the_game_limit() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start_with(
+        &mock,
+        &[("lichess_max_games", "1"), ("lichess_challenge_queue", "1")],
+    );
+
+    mock.push_event(CHALLENGE);
+    mock.wait_for_request("POST /api/challenge/chal1234/accept")
+        .await;
+    mock.push_event(&CHALLENGE.replace("chal1234", "queued12"));
+    mock.push_event(&CHALLENGE.replace("chal1234", "busy1234"));
+    let decline = mock
+        .wait_for_request("POST /api/challenge/busy1234/decline")
+        .await;
+    assert_eq!(decline.body, "reason=later");
+    assert!(!mock
+        .requests()
+        .iter()
+        .any(|r| r.path.starts_with("/api/challenge/queued12/")));
+
+    mock.push_event(
+        &GAME_START
+            .replace("gameStart", "gameFinish")
+            .replace("game1234", "chal1234"),
+    );
+    mock.wait_for_request("POST /api/challenge/queued12/accept")
+        .await;
+}
+
+#[tokio::test]
+async fn reconnects_dropped_streams_and_resumes_the_game() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start(&mock);
+
+    mock.push_event(GAME_START);
+    mock.wait_for_request("GET /api/bot/game/stream/game1234")
+        .await;
+    mock.drop_events();
+    mock.wait_for_requests("GET /api/stream/event", 2).await;
+
+    // The reconnected event stream repeats the running game, it is not started twice
+    mock.push_event(GAME_START);
+    mock.drop_game("game1234");
+    mock.wait_for_requests("GET /api/bot/game/stream/game1234", 2)
+        .await;
+    mock.push_game("game1234", GAME_FULL);
+    mock.wait_for_request("POST /api/bot/game/game1234/move/")
+        .await;
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let streams = mock
+        .requests()
+        .iter()
+        .filter(|r| r.line() == "GET /api/bot/game/stream/game1234")
+        .count();
+    assert_eq!(streams, 2);
+}
+
+#[tokio::test]
+async fn retries_after_server_errors() {
+    let mock = MockLichess::start().await;
+    mock.respond_once("GET /api/account", 503, "");
+    mock.respond_once("POST /api/bot/game/game1234/move/a2a3", 502, "");
+    let _bot = Bot::start(&mock);
+
+    mock.wait_for_requests("GET /api/account", 2).await;
+    mock.push_event(GAME_START);
+    // a2a3 is the only legal move
+    mock.push_game(
+        "game1234",
+        &GAME_FULL.replace(
+            r#""initialFen":"startpos""#,
+            r#""initialFen":"7k/8/8/8/p7/8/P4q2/7K w - - 0 1""#,
+        ),
+    );
+    let posts = mock
+        .wait_for_requests("POST /api/bot/game/game1234/move/", 2)
+        .await;
+    assert!(posts.iter().all(|post| post.path.ends_with("/a2a3")));
+}
+
+#[tokio::test]
+async fn greets_and_answers_chat_commands() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start_with(&mock, &[("lichess_chat_greeting", "Hello")]);
+
+    mock.push_event(GAME_START);
+    mock.push_game("game1234", GAME_FULL);
+    let greetings = mock
+        .wait_for_requests("POST /api/bot/game/game1234/chat", 2)
+        .await;
+    let bodies = greetings
+        .iter()
+        .map(|r| r.body.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bodies,
+        vec!["room=player&text=Hello", "room=spectator&text=Hello"]
+    );
+
+    mock.push_game(
+        "game1234",
+        r#"{"type":"chatLine","username":"Someone","text":"!help","room":"spectator"}"#,
+    );
+    let chat = mock
+        .wait_for_requests("POST /api/bot/game/game1234/chat", 3)
+        .await;
+    assert!(chat[2].body.starts_with("room=spectator&text=Commands"));
+}
+
+#[tokio::test]
+async fn resigns_lost_games_and_declines_unwanted_draws() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start_with(
+        &mock,
+        &[
+            ("lichess_resign_score", "500"),
+            ("lichess_resign_moves", "1"),
+        ],
+    );
+
+    // A bare king against queen and rook
+    mock.push_event(&GAME_START.replace("game1234", "lost1234"));
+    mock.push_game(
+        "lost1234",
+        &GAME_FULL.replace("game1234", "lost1234").replace(
+            r#""initialFen":"startpos""#,
+            r#""initialFen":"kq6/r7/8/8/8/8/8/4K3 w - - 0 1""#,
+        ),
+    );
+    mock.wait_for_request("POST /api/bot/game/lost1234/resign")
+        .await;
+
+    // A level opening is no reason to agree a draw
+    mock.push_event(GAME_START);
+    mock.push_game(
+        "game1234",
+        &GAME_FULL.replace(
+            r#""status":"started""#,
+            r#""status":"started","bdraw":true"#,
+        ),
+    );
+    mock.wait_for_request("POST /api/bot/game/game1234/draw/no")
+        .await;
+    mock.wait_for_request("POST /api/bot/game/game1234/move/")
+        .await;
+    assert!(!mock
+        .requests()
+        .iter()
+        .any(|r| r.path.starts_with("/api/bot/game/lost1234/move/")));
+}
+
+#[tokio::test]
+async fn grants_takebacks_and_replays_from_the_shorter_game() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start_with(&mock, &[("lichess_takebacks", "casual")]);
+
+    mock.push_event(GAME_START);
+    mock.push_game("game1234", GAME_FULL);
+    let first = mock
+        .wait_for_request("POST /api/bot/game/game1234/move/")
+        .await;
+    let first_move = first.path.rsplit('/').next().unwrap().to_string();
+
+    let state = |moves: &str, btakeback: bool| {
+        format!(
+            r#"{{"type":"gameState","moves":"{}","wtime":2500,"btime":3000,"winc":0,"binc":0,"status":"started","btakeback":{}}}"#,
+            moves, btakeback
+        )
+    };
+    mock.push_game("game1234", &state(&format!("{} e7e5", first_move), true));
+    mock.wait_for_request("POST /api/bot/game/game1234/takeback/yes")
+        .await;
+    // Both moves are undone and the bot is on move again
+    mock.push_game("game1234", &state("", false));
+    let moves = mock
+        .wait_for_requests("POST /api/bot/game/game1234/move/", 2)
+        .await;
+    let second_move = moves[1].path.rsplit('/').next().unwrap().to_string();
+
+    // Only one takeback per game by default
+    mock.push_game("game1234", &state(&format!("{} e7e5", second_move), true));
+    mock.wait_for_request("POST /api/bot/game/game1234/takeback/no")
+        .await;
+    mock.wait_for_requests("POST /api/bot/game/game1234/move/", 3)
+        .await;
+}
+
+#[tokio::test]
+async fn aborts_or_claims_when_the_opponent_is_absent() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start_with(&mock, &[("lichess_abort_seconds", "1")]);
+
+    // The opponent never answers the bot's first move
+    mock.push_event(GAME_START);
+    mock.push_game("game1234", GAME_FULL);
+    let first = mock
+        .wait_for_request("POST /api/bot/game/game1234/move/")
+        .await;
+    mock.push_game(
+        "game1234",
+        &format!(
+            r#"{{"type":"gameState","moves":"{}","wtime":2500,"btime":3000,"winc":0,"binc":0,"status":"started"}}"#,
+            first.path.rsplit('/').next().unwrap()
+        ),
+    );
+    mock.wait_for_request("POST /api/bot/game/game1234/abort")
+        .await;
+
+    // Lichess allows a claim straight away once the opponent has left
+    mock.push_event(&GAME_START.replace("game1234", "gone1234"));
+    mock.push_game(
+        "gone1234",
+        &GAME_FULL
+            .replace("game1234", "gone1234")
+            .replace(r#""moves":"""#, r#""moves":"e2e4 e7e5""#),
+    );
+    mock.wait_for_request("POST /api/bot/game/gone1234/move/")
+        .await;
+    mock.push_game(
+        "gone1234",
+        r#"{"type":"opponentGone","gone":true,"claimWinInSeconds":0}"#,
+    );
+    mock.wait_for_request("POST /api/bot/game/gone1234/claim-victory")
+        .await;
+    assert!(!mock
+        .requests()
+        .iter()
+        .any(|r| r.path == "/api/bot/game/gone1234/abort"));
+}
+
+#[tokio::test]
+async fn abandons_games_it_cannot_follow() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start(&mock);
+    mock.wait_for_request("GET /api/stream/event").await;
+
+    // e2e5 is no legal reply, every position after it would be wrong
+    mock.push_game(
+        "game1234",
+        &GAME_FULL.replace(r#""moves":"""#, r#""moves":"e2e4 e2e5""#),
+    );
+    mock.push_event(GAME_START);
+    mock.push_game(
+        "badfen12",
+        &GAME_FULL
+            .replace("game1234", "badfen12")
+            .replace(r#""initialFen":"startpos""#, r#""initialFen":"not a fen""#),
+    );
+    mock.push_event(&GAME_START.replace("game1234", "badfen12"));
+
+    // The bot stays up and plays its other games
+    mock.push_game("good1234", &GAME_FULL.replace("game1234", "good1234"));
+    mock.push_event(&GAME_START.replace("game1234", "good1234"));
+    mock.wait_for_request("POST /api/bot/game/good1234/move/")
+        .await;
+    mock.wait_for_request("GET /api/bot/game/stream/badfen12")
+        .await;
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    assert!(!mock.requests().iter().any(|r| {
+        r.path.starts_with("/api/bot/game/game1234/move/")
+            || r.path.starts_with("/api/bot/game/badfen12/move/")
+    }));
+}
+
+#[tokio::test]
+async fn frees_the_slot_when_game_finish_is_lost() {
+    let mock = MockLichess::start().await;
+    let _bot = Bot::start(&mock);
+
+    mock.push_event(&CHALLENGE.replace("chal1234", "game1234"));
+    mock.wait_for_request("POST /api/challenge/game1234/accept")
+        .await;
+    mock.push_event(GAME_START);
+    mock.wait_for_request("GET /api/bot/game/stream/game1234")
+        .await;
+
+    // The game ends while the event stream is down, gameFinish never arrives
+    mock.drop_events();
+    mock.push_game(
+        "game1234",
+        &GAME_FULL.replace(r#""status":"started""#, r#""status":"mate""#),
+    );
+    mock.drop_game("game1234");
+    mock.wait_for_requests("GET /api/stream/event", 2).await;
+
+    mock.push_event(CHALLENGE);
+    mock.wait_for_request("POST /api/challenge/chal1234/accept")
+        .await;
+    assert!(!mock
+        .requests()
+        .iter()
+        .any(|r| r.path.starts_with("/api/challenge/chal1234/decline")));
+}
+
+#[tokio::test]
+async fn backs_off_when_the_event_stream_keeps_dropping() {
+    let mock = MockLichess::start().await;
+    // Every connection opens fine and closes at once
+    mock.respond("GET /api/stream/event", 200, "");
+    let _bot = Bot::start(&mock);
+
+    mock.wait_for_requests("GET /api/stream/event", 3).await;
+    let third = std::time::Instant::now();
+    mock.wait_for_requests("GET /api/stream/event", 4).await;
+    // The third delay is at least two seconds, the first one at most one
+    assert!(third.elapsed() >= std::time::Duration::from_millis(1500));
+}
+
+#[tokio::test]
+async fn stops_when_lichess_refuses_the_token() {
+    let mock = MockLichess::start().await;
+    mock.respond("GET /api/stream/event", 401, r#"{"error":"No such token"}"#);
+    let mut bot = Bot::start(&mock);
+
+    mock.wait_for_request("GET /api/stream/event").await;
+    let start = std::time::Instant::now();
+    while bot.0.try_wait().unwrap().is_none() {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let attempts = mock
+        .requests()
+        .iter()
+        .filter(|r| r.line() == "GET /api/stream/event")
+        .count();
+    assert_eq!(attempts, 1);
+}
+
+#[tokio::test]
+async fn reopens_the_game_when_a_move_is_lost() {
+    let mock = MockLichess::start().await;
+    for _ in 0..3 {
+        mock.respond_once("POST /api/bot/game/game1234/move/a2a3", 502, "");
+    }
+    let _bot = Bot::start(&mock);
+
+    mock.push_event(GAME_START);
+    // a2a3 is the only legal move
+    let game = GAME_FULL.replace(
+        r#""initialFen":"startpos""#,
+        r#""initialFen":"7k/8/8/8/p7/8/P4q2/7K w - - 0 1""#,
+    );
+    mock.push_game("game1234", &game);
+    mock.wait_for_requests("POST /api/bot/game/game1234/move/", 3)
+        .await;
+
+    // Lichess never saw the move, the reopened stream shows it is still our turn
+    mock.wait_for_requests("GET /api/bot/game/stream/game1234", 2)
+        .await;
+    mock.push_game("game1234", &game);
+    let posts = mock
+        .wait_for_requests("POST /api/bot/game/game1234/move/", 4)
+        .await;
+    assert!(posts.iter().all(|post| post.path.ends_with("/a2a3")));
+}
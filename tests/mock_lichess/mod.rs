@@ -0,0 +1,240 @@
+// A stand-in for the parts of the Lichess API the bot talks to. Streams are
+// fed by the test, every request is recorded so the test can wait for the
+// bot to react.
+use hyper::body::Bytes;
+use hyper::service::make_service_fn;
+use hyper::service::service_fn;
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use hyper::Server;
+use hyper::StatusCode;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_ACCOUNT: &str = r#"{"id":"gambinobot","username":"GambinoBot","title":"BOT"}"#;
+
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub authorization: Option<String>,
+    pub user_agent: Option<String>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn line(&self) -> String {
+        format!("{} {}", self.method, self.path)
+    }
+}
+
+#[derive(Default)]
+struct NdjsonStream {
+    // Lines pushed before anyone connected
+    pending: Vec<String>,
+    connections: Vec<mpsc::UnboundedSender<String>>,
+}
+
+#[derive(Default)]
+struct State {
+    requests: Vec<RecordedRequest>,
+    streams: HashMap<String, NdjsonStream>,
+    // Canned (status, body) replies keyed by "METHOD /path"
+    responses: HashMap<String, (u16, String)>,
+    // Replies used up one at a time before `responses` applies
+    one_off: HashMap<String, Vec<(u16, String)>>,
+}
+
+pub struct MockLichess {
+    pub base_url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockLichess {
+    pub async fn start() -> Self {
+        let mut state = State::default();
+        state.responses.insert(
+            "GET /api/account".to_string(),
+            (200, DEFAULT_ACCOUNT.to_string()),
+        );
+        let state = Arc::new(Mutex::new(state));
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(handle(&state, request).await) }
+                }))
+            }
+        });
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = Server::bind(&addr).serve(make_service);
+        let base_url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        MockLichess { base_url, state }
+    }
+
+    pub fn push_event(&self, line: &str) {
+        self.push("/api/stream/event", line);
+    }
+
+    pub fn push_game(&self, game_id: &str, line: &str) {
+        self.push(&format!("/api/bot/game/stream/{}", game_id), line);
+    }
+
+    // Ends the open connections of a stream, as a network drop would
+    pub fn drop_events(&self) {
+        self.drop_stream("/api/stream/event");
+    }
+
+    pub fn drop_game(&self, game_id: &str) {
+        self.drop_stream(&format!("/api/bot/game/stream/{}", game_id));
+    }
+
+    fn drop_stream(&self, path: &str) {
+        if let Some(stream) = self.state.lock().unwrap().streams.get_mut(path) {
+            stream.connections.clear();
+        }
+    }
+
+    fn push(&self, path: &str, line: &str) {
+        let mut state = self.state.lock().unwrap();
+        let stream = state.streams.entry(path.to_string()).or_default();
+        let line = format!("{}\n", line);
+        stream
+            .connections
+            .retain(|connection| connection.send(line.clone()).is_ok());
+        if stream.connections.is_empty() {
+            stream.pending.push(line);
+        }
+    }
+
+    pub fn respond(&self, request: &str, status: u16, body: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .insert(request.to_string(), (status, body.to_string()));
+    }
+
+    pub fn respond_once(&self, request: &str, status: u16, body: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .one_off
+            .entry(request.to_string())
+            .or_default()
+            .push((status, body.to_string()));
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    // Waits until `count` requests starting with `prefix` (e.g. "POST /api/...") arrived
+    pub async fn wait_for_requests(&self, prefix: &str, count: usize) -> Vec<RecordedRequest> {
+        let start = Instant::now();
+        loop {
+            let matching = self
+                .requests()
+                .into_iter()
+                .filter(|r| r.line().starts_with(prefix))
+                .collect::<Vec<RecordedRequest>>();
+            if matching.len() >= count {
+                return matching;
+            }
+            if start.elapsed() > WAIT_TIMEOUT {
+                panic!(
+                    "timed out waiting for {} x {}, saw {:?}",
+                    count,
+                    prefix,
+                    self.requests().iter().map(|r| r.line()).collect::<Vec<_>>()
+                );
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    pub async fn wait_for_request(&self, prefix: &str) -> RecordedRequest {
+        self.wait_for_requests(prefix, 1).await.remove(0)
+    }
+}
+
+async fn handle(state: &Arc<Mutex<State>>, request: Request<Body>) -> Response<Body> {
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let authorization = header("authorization");
+    let user_agent = header("user-agent");
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .unwrap_or_default();
+    let recorded = RecordedRequest {
+        method,
+        path,
+        authorization,
+        user_agent,
+        body: String::from_utf8_lossy(&body).to_string(),
+    };
+    let mut state = state.lock().unwrap();
+    state.requests.push(recorded.clone());
+
+    let one_off = state
+        .one_off
+        .get_mut(&recorded.line())
+        .filter(|replies| !replies.is_empty())
+        .map(|replies| replies.remove(0));
+    if let Some((status, body)) = one_off.or_else(|| state.responses.get(&recorded.line()).cloned())
+    {
+        return Response::builder()
+            .status(status)
+            .body(Body::from(body))
+            .unwrap();
+    }
+
+    let is_stream =
+        recorded.path == "/api/stream/event" || recorded.path.starts_with("/api/bot/game/stream/");
+    if recorded.method == "GET" && is_stream {
+        let stream = state.streams.entry(recorded.path).or_default();
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        for line in stream.pending.drain(..) {
+            let _ = tx.send(line);
+        }
+        stream.connections.push(tx);
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                if sender.send_data(Bytes::from(line)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        return Response::builder()
+            .header("content-type", "application/x-ndjson")
+            .body(body)
+            .unwrap();
+    }
+
+    if recorded.method == "POST" {
+        return Response::new(Body::from("{\"ok\":true}"));
+    }
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::from("{\"error\":\"Not found\"}"))
+        .unwrap()
+}